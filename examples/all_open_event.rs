@@ -10,7 +10,7 @@ fn main() {
     }
     let fd = fd.as_ref().unwrap();
     let status = mark(
-        &fd,
+        fd,
         FAN_MARK_ADD | FAN_MARK_MOUNT,
         FAN_OPEN | FAN_EVENT_ON_CHILD,
         AT_FDCWD,
@@ -20,11 +20,11 @@ fn main() {
     if status.is_err() {
         eprintln!("Encountered err due to {fd:?}");
     }
-    let _status = status.unwrap();
+    status.unwrap();
 
     loop {
         // read_do(fd, print_meta).unwrap();
-        let data = read(&fd).unwrap();
+        let data = read(fd).unwrap();
         println!("{:#?}", data);
     }
 }
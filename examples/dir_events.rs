@@ -2,6 +2,7 @@ use naughtyfy::api::*;
 use naughtyfy::flags::*;
 use naughtyfy::types::Fd;
 use naughtyfy::types::FdToPath;
+use std::ops::ControlFlow;
 
 /// Using naughtyfy to report(print) all
 /// file access, modify, close, open events (for files)
@@ -30,13 +31,22 @@ fn main() {
     if status.is_err() {
         eprintln!("Encountered err due to {status:#?}");
     }
-    let _status = status.unwrap();
+    status.unwrap();
 
     loop {
-        read_do(fd, |md| {
-            let path = Fd::path_from_rawfd(md.fd);
+        let flow = read_do(fd, |md| {
+            let path = md.fd().map(Fd::path);
             println!("{:?} at {:?}", md.mask, path);
+            // Stop once the queue overflowed
+            if md.mask & FAN_Q_OVERFLOW != 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
         })
         .unwrap();
+        if flow.is_break() {
+            break;
+        }
     }
 }
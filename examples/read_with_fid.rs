@@ -1,10 +1,12 @@
 use naughtyfy::api::*;
+use naughtyfy::event::Event;
 use naughtyfy::flags::*;
-use naughtyfy::types::*;
+use std::ops::ControlFlow;
 
 /// What to do with the event?
-fn procedure(md: &fanotify_event_with_fid) {
+fn procedure(md: &Event) -> ControlFlow<()> {
     println!("{md:#?}");
+    ControlFlow::Continue(())
 }
 
 /// Run this example with sudo privilages and create
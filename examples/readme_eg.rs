@@ -2,10 +2,6 @@ use naughtyfy::api::*;
 use naughtyfy::flags::*;
 
 fn main() {
-    unsafe {
-        // Use carefully in multi thread.
-        FAN_EVENT_BUFFER_LEN = 230.into();
-    }
     // Initialise fanotify
     let fd = &init(FAN_CLASS_NOTIF, 0).unwrap();
 
@@ -15,9 +11,9 @@ fn main() {
     // Try extracting events from the buffer and print it
     let mut iter = 1;
     loop {
+        // Event fds are closed when `res` is dropped
         let res = read(fd).unwrap();
         println!("{res:#?}");
-        res.iter().for_each(|e| close(e.fd).unwrap());
         iter += 1;
         if iter > 100 {
             break;
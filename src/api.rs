@@ -1,32 +1,38 @@
 //! Low level function mapping for fanotify
 
-use crate::{errors::*, types::*};
+use crate::{
+    errors::*,
+    event::*,
+    mark::{c_path, MarkTarget},
+    mask::*,
+    types::*,
+    validate::check_target_flags,
+};
 use libc::c_void;
 use std::{
-    ffi::CString,
-    io::Error,
+    ffi::CStr,
+    ffi::OsStr,
     mem,
+    ops::ControlFlow,
     os::{
         fd::{AsRawFd, FromRawFd, OwnedFd as Fd},
         unix::ffi::OsStrExt,
     },
+    path::PathBuf,
 };
 
 // Used for docs test
 #[allow(unused_imports)]
-use crate::flags::*;
-
-/// Get current platform sizeof of [`fanotify_event_metadata`].
-const FAN_EVENT_METADATA_LEN: usize = mem::size_of::<fanotify_event_metadata>();
-
-/// Get current platform sizeof of [`fanotify_event_with_fid`].
-const FAN_EVENT_METADATA_FID_LEN: usize = mem::size_of::<fanotify_event_with_fid>();
+use crate::{flags::*, group::Fanotify};
 
 /// Get current platform size of [`fanotify_response`]
 const FAN_WRITE_RESPONSE_LEN: usize = mem::size_of::<fanotify_response>();
 
-/// Length of memory to be allocated for read buffer
-pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex::new(250);
+/// Size in bytes of the buffer allocated by every call to [`read()`],
+/// room for 250 events without information records.
+///
+/// Use [`Fanotify`] to choose the size per group and reuse the buffer across reads.
+pub const DEFAULT_EVENT_BUFFER_LEN: usize = 250 * FAN_EVENT_METADATA_LEN;
 
 /// Initializes a new fanotify group and returns a
 /// file descriptor [`Fd`] for the event queue associated
@@ -77,11 +83,11 @@ pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex:
 ///     * [`FAN_REPORT_NAME`]
 ///     * [`FAN_REPORT_DFID_NAME`]
 /// * `event_f_flags` - Defines the file status flags that
-///                     will be set on the open file descriptions that are created for
-///                     fanotify events.  For details of these flags, see the description
-///                     of the flags values in open(2).  `event_f_flags` includes a multi-
-///                     bit field for the access mode. This field can take the following
-///                     values:
+///   will be set on the open file descriptions that are created for
+///   fanotify events.  For details of these flags, see the description
+///   of the flags values in open(2).  `event_f_flags` includes a multi-
+///   bit field for the access mode. This field can take the following
+///   values:
 ///     * [`O_RDONLY`]
 ///     * [`O_WRONLY`]
 ///     * [`O_RDWR`]
@@ -113,11 +119,29 @@ pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex:
 /// ```
 ///
 pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
+    init_with(InitFlags::from(flags), EventFFlags::from(event_f_flags))
+}
+
+/// Same as [`init()`] with typed flags.
+///
+/// # Example
+/// This example may thorw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::mask::*;
+/// let fd = init_with(InitFlags::CLASS_NOTIF | InitFlags::NONBLOCK, EventFFlags::RDONLY);
+/// if let Err(e) = fd {
+///     eprintln!("Cannot get fd due to {e}");
+/// }
+/// ```
+pub fn init_with(flags: InitFlags, event_f_flags: EventFFlags) -> Result<Fd, FanotifyError> {
     unsafe {
-        match libc::fanotify_init(flags, event_f_flags) {
-            -1 => Err(FanotifyError::Init(
-                Error::last_os_error().raw_os_error().unwrap_or_default(),
-            )),
+        match libc::fanotify_init(flags.bits(), event_f_flags.bits()) {
+            -1 => Err(FanotifyError::Init {
+                errno: Errno::last(),
+                flags,
+                event_f_flags,
+            }),
             fd => Ok(Fd::from_raw_fd(fd)),
         }
     }
@@ -130,13 +154,13 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 /// # Arguments
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
 /// * `flags` - Bit mask describing the modification to perform. <br>
-///     It must include **exactly one** of the following values:
+///   It must include **exactly one** of the following values:
 ///     * [`FAN_MARK_ADD`]
 ///     * [`FAN_MARK_REMOVE`]
 ///     * [`FAN_MARK_FLUSH`]
 ///
-///      In addition, zero or more of the following values may be ORed
-///      into flags:
+///   In addition, zero or more of the following values may be ORed
+///   into flags:
 ///     * [`FAN_MARK_DONT_FOLLOW`]
 ///     * [`FAN_MARK_ONLYDIR`]
 ///     * [`FAN_MARK_MOUNT`]
@@ -144,7 +168,7 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 ///     * [`FAN_MARK_IGNORED_MASK`]
 ///     * [`FAN_MARK_IGNORED_SURV_MODIFY`]
 /// * `mask` - Which events shall be listened for (or which shall be ignored). <br>
-///     It is a bit mask composed of the following values:
+///   It is a bit mask composed of the following values:
 ///     * [`FAN_ACCESS`]
 ///     * [`FAN_MODIFY`]
 ///     * [`FAN_CLOSE_WRITE`]
@@ -166,7 +190,8 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 ///     * [`FAN_CLOSE`]
 ///     * [`FAN_MOVE`]
 /// * `dirfd` - Defines the filesystem object to be marked.
-/// * `path` - Filesystem path of file or diretory.
+/// * `path` - Filesystem path of file or diretory. A path containing a NUL
+///   byte fails with [`FanotifyError::InvalidPath`].
 ///
 /// The filesystem object to be marked is determined by the file
 /// descriptor dirfd and the pathname specified in path:
@@ -175,15 +200,15 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 ///   marked.
 /// * If pathname is `NULL`, and dirfd takes the special value
 ///   [`AT_FDCWD`], the current working directory is to be marked.
-
+///
 /// * If pathname is absolute, it defines the filesystem object to
 ///   be marked, and dirfd is ignored.
-
+///
 /// * If pathname is relative, and dirfd does not have the value
 ///   [`AT_FDCWD`], then the filesystem object to be marked is
 ///   determined by interpreting pathname relative the directory
 ///   referred to by dirfd.
-
+///
 /// * If pathname is relative, and dirfd has the value [`AT_FDCWD`],
 ///   then the filesystem object to be marked is determined by
 ///   interpreting pathname relative to the current working
@@ -216,23 +241,104 @@ pub fn mark<P: ?Sized + Path>(
     dirfd: i32,
     path: &P,
 ) -> Result<(), FanotifyError> {
-    let path = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+    mark_with(
+        fd,
+        MarkFlags::from(flags),
+        EventMask::from(mask),
+        dirfd,
+        path,
+    )
+}
+
+/// Same as [`mark()`] with typed flags and mask.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::mask::*;
+/// if let Ok(fd) = &init_with(InitFlags::CLASS_NOTIF, EventFFlags::RDONLY) {
+///     let m = mark_with(fd, MarkFlags::ADD | MarkFlags::MOUNT, EventMask::ACCESS, AT_FDCWD, "./");
+///     assert!(m.is_ok());
+/// }
+/// ```
+pub fn mark_with<P: ?Sized + Path>(
+    fd: &Fd,
+    flags: MarkFlags,
+    mask: EventMask,
+    dirfd: i32,
+    path: &P,
+) -> Result<(), FanotifyError> {
+    let path = c_path(path.as_os_str())?;
+    mark_raw(fd, flags, mask, dirfd, Some(&path))
+}
+
+/// Same as [`mark_with()`] with the object to mark and its kind given by
+/// `target`, `flags` must not hold [`FAN_MARK_MOUNT`] or [`FAN_MARK_FILESYSTEM`]
+/// or [`FanotifyError::Invalid`] is returned.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// if let Ok(fd) = &init_with(InitFlags::CLASS_NOTIF, EventFFlags::RDONLY) {
+///     let m = mark_target(fd, MarkFlags::ADD, EventMask::ACCESS, MarkTarget::mount("./"));
+///     assert!(m.is_ok());
+/// }
+/// ```
+pub fn mark_target(
+    fd: &Fd,
+    flags: MarkFlags,
+    mask: EventMask,
+    target: MarkTarget,
+) -> Result<(), FanotifyError> {
+    check_target_flags(flags)?;
+    let path = target.path()?;
+    mark_raw(
+        fd,
+        flags | target.flags(),
+        mask,
+        target.dirfd(),
+        path.as_deref(),
+    )
+}
+
+/// Calls fanotify_mark(2), `path` is passed as `NULL` when `None`.
+fn mark_raw(
+    fd: &Fd,
+    flags: MarkFlags,
+    mask: EventMask,
+    dirfd: i32,
+    path: Option<&CStr>,
+) -> Result<(), FanotifyError> {
     unsafe {
-        match libc::fanotify_mark(fd.as_raw_fd(), flags, mask, dirfd, path.as_ptr()) {
+        match libc::fanotify_mark(
+            fd.as_raw_fd(),
+            flags.bits(),
+            mask.bits(),
+            dirfd,
+            path.map_or(std::ptr::null(), CStr::as_ptr),
+        ) {
             0 => Ok(()),
-            _ => Err(FanotifyError::Mark(
-                Error::last_os_error().raw_os_error().unwrap_or_default(),
-            )),
+            _ => Err(FanotifyError::Mark {
+                errno: Errno::last(),
+                flags,
+                mask,
+                path: path.map(|path| PathBuf::from(OsStr::from_bytes(path.to_bytes()))),
+            }),
         }
     }
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
-/// into a `Vec<fanotify_event_metadata>` and return a Result.
+/// into a `Vec<Event>` and return a Result.
 ///
 /// # Note
-/// [`close()`] is called on metadata's fd when [`fanotify_event_metadata`]
-/// is dropped. No need to explicitly call [`close()`] on every fd.
+/// Every [`Event`] owns its fd and closes it when dropped.
+/// Don't call [`close()`] on it, use [`Event::take_fd()`] to keep it open.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
@@ -249,8 +355,8 @@ pub fn mark<P: ?Sized + Path>(
 ///         let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
 ///         let res = read(fd);
 ///         assert!(res.is_ok());
-///         for meta in res.unwrap() {
-///             close(meta.fd).unwrap();
+///         for event in res.unwrap() {
+///             println!("{:?}", event.fd().map(|fd| fd.path()));
 ///         }
 ///     }
 ///     Err(e) => {
@@ -260,52 +366,27 @@ pub fn mark<P: ?Sized + Path>(
 ///     }
 /// }
 /// ```
-pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_metadata> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_LEN * len,
-        );
-    }
-
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
-    }
-
-    unsafe {
-        // Vec.set_len() is unsafe operation. len must be less than capacity, which is gur
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_LEN);
-    }
-    Ok(buff)
+pub fn read(fd: &Fd) -> Result<Vec<Event>, FanotifyError> {
+    let mut buff = Vec::with_capacity(DEFAULT_EVENT_BUFFER_LEN);
+    read_buffer(fd, &mut buff)?;
+    // Safety: `buff` was just filled by read(2) on the group.
+    unsafe { EventIter::new(&buff) }.collect()
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
-/// and performs `process_metadata` on [`fanotify_event_metadata`] recieved after read.
-/// returns `Result<(),FanotifyError>`.
+/// and performs `process_metadata` on every [`Event`] recieved after read.
+/// returns `Result<ControlFlow<B>,FanotifyError>`.
 ///
-/// This function closes `metadata.fd` after calling `process_metadata`
+/// `process_metadata` may hold state and returns [`ControlFlow::Break`] to
+/// stop processing the batch early, the value it breaks with is returned
+/// as `Ok(ControlFlow::Break(value))`. Use it to pass a handler error out.
+///
+/// This function closes the event fd after calling `process_metadata`,
+/// fds of the events skipped by an early exit are closed as well.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
-/// * `process_metadata` - Function / Closure for processing [`fanotify_event_metadata`].
+/// * `process_metadata` - Function / Closure for processing [`Event`].
 ///
 /// # Example
 /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
@@ -313,18 +394,25 @@ pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
 /// # use naughtyfy::flags::*;
 /// # use naughtyfy::types::*;
 /// # use naughtyfy::api::*;
-/// fn procedure(md: &fanotify_event_metadata) {
-///     println!("{md:#?}");
-/// }
+/// # use naughtyfy::event::*;
+/// use std::ops::ControlFlow;
 ///
 /// fn main() {
 ///     let fd = &init(FAN_CLASS_NOTIF, 0);
 ///      match fd {
 ///          Ok(fd) => {
 ///              let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
-///              let res = read(fd);
+///              let mut seen = 0;
+///              // Stop at the first event that has no fd
+///              let res = read_do(fd, |md| {
+///                  seen += 1;
+///                  match md.fd() {
+///                      Some(_) => ControlFlow::Continue(()),
+///                      None => ControlFlow::Break(md.mask),
+///                  }
+///              });
 ///              assert!(res.is_ok());
-///              read_do(fd,procedure);
+///              assert!(seen > 0);
 ///          }
 ///          Err(e) => {
 ///              // This can fail for multiple reason, most common being privileges.
@@ -334,142 +422,119 @@ pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
 ///      }
 /// }
 /// ```
-pub fn read_do(
-    fd: &Fd,
-    process_metadata: fn(&fanotify_event_metadata),
-) -> Result<(), FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
+pub fn read_do<B, F>(fd: &Fd, mut process_metadata: F) -> Result<ControlFlow<B>, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<B>,
+{
+    let mut buff = Vec::with_capacity(DEFAULT_EVENT_BUFFER_LEN);
+    read_buffer(fd, &mut buff)?;
+    // Safety: `buff` was just filled by read(2) on the group.
+    let mut events = unsafe { EventIter::new(&buff) };
+    for event in events.by_ref() {
+        if let ControlFlow::Break(value) = process_metadata(&event?) {
+            // Dropping the rest closes their fds.
+            events.for_each(drop);
+            return Ok(ControlFlow::Break(value));
         }
     }
-    let mut buff: Vec<fanotify_event_metadata> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_LEN * len,
-        );
-    }
+    Ok(ControlFlow::Continue(()))
+}
 
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
-    }
+/// Same as [`read()`] without waiting for events, returns an empty
+/// [`Vec`] instead of failing with `EAGAIN` (`EWOULDBLOCK`).
+///
+/// The read is sized to the pending bytes reported by `FIONREAD`, nothing
+/// is read when the queue is empty, so this never blocks even on a group
+/// initialised without [`FAN_NONBLOCK`]. A read interrupted by a signal
+/// (`EINTR`) is retried.
+///
+/// # Note
+/// Kernel counts only the [metadata](fanotify_event_metadata) of every
+/// event in `FIONREAD`, for a group that reports information records the
+/// buffer grows until the first event fits and the events that didn't fit
+/// are left for the next read.
+///
+/// # Argument
+/// * `fd` - Refrence to [`Fd`] returned by [`init()`]
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::types::*;
+/// # use naughtyfy::api::*;
+/// match &init(FAN_CLASS_NOTIF, O_RDONLY) {
+///     Ok(fd) => {
+///         // Group is blocking, nothing is marked so nothing is pending.
+///         assert!(try_read(fd).unwrap().is_empty());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub fn try_read(fd: &Fd) -> Result<Vec<Event>, FanotifyError> {
+    let mut buff = Vec::new();
+    try_read_buffer(fd, &mut buff, DEFAULT_EVENT_BUFFER_LEN)?;
+    // Safety: `buff` was just filled by read(2) on the group.
+    unsafe { EventIter::new(&buff) }.collect()
+}
 
-    unsafe {
-        // Vec.set_len() is unsafe operation. len must be less than capacity, which is gur
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_LEN);
-    }
-    for event in &buff {
-        process_metadata(event);
+/// Same as [`read_do()`] without waiting for events, `process_metadata`
+/// isn't called when nothing is pending, see [`try_read()`].
+pub fn try_read_do<B, F>(fd: &Fd, mut process_metadata: F) -> Result<ControlFlow<B>, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<B>,
+{
+    let mut buff = Vec::new();
+    try_read_buffer(fd, &mut buff, DEFAULT_EVENT_BUFFER_LEN)?;
+    // Safety: `buff` was just filled by read(2) on the group.
+    let mut events = unsafe { EventIter::new(&buff) };
+    for event in events.by_ref() {
+        if let ControlFlow::Break(value) = process_metadata(&event?) {
+            // Dropping the rest closes their fds.
+            events.for_each(drop);
+            return Ok(ControlFlow::Break(value));
+        }
     }
-    Ok(())
+    Ok(ControlFlow::Continue(()))
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
-/// into a [`Vec`] of [`fanotify_event_with_fid`] which was initilated with
+/// into a [`Vec`] of [`Event`] which was initilated with
 /// [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag. Returns the vector wrapped in `Result`.
 ///
-/// # Important
-/// Use this only when `fd` is initialized with [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag.
+/// Every [`Event`] holds the information records that followed its
+/// metadata in [`Event::info`], records are walked using `event_len`
+/// so events of different length can be mixed in the same read.
+///
+/// # Note
+/// Same as [`read()`], which decodes information records as well.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
-pub fn read_with_fid(fd: &Fd) -> Result<Vec<fanotify_event_with_fid>, FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_with_fid> = Vec::with_capacity(len);
-
-    let sizeof;
-    unsafe {
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_FID_LEN * len,
-        );
-    }
-
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
-    }
-
-    unsafe {
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_FID_LEN);
-    }
-    Ok(buff)
+pub fn read_with_fid(fd: &Fd) -> Result<Vec<Event>, FanotifyError> {
+    read(fd)
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
 /// which was initilated with [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag
-/// and performs `process_metadata_fid` on [`fanotify_event_with_fid`]
-/// recieved after read. Returns `Result<(),FanotifyError>`.
+/// and performs `process_metadata_fid` on [`Event`]
+/// recieved after read. Returns `Result<ControlFlow<B>,FanotifyError>`.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`].
-/// * `process_metadata` - Function / Closure for processing [`fanotify_event_with_fid`].
-pub fn read_with_fid_do(
+/// * `process_metadata` - Function / Closure for processing [`Event`],
+///   see [`read_do()`] for early exit.
+pub fn read_with_fid_do<B, F>(
     fd: &Fd,
-    process_metadata_fid: fn(&fanotify_event_with_fid),
-) -> Result<(), FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_with_fid> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_FID_LEN * len,
-        );
-    }
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
-    }
-
-    // Vec.set_len() is unsafe operation. len must be less than capacity.
-    unsafe {
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_FID_LEN);
-    }
-    for event in &buff {
-        process_metadata_fid(event);
-    }
-    Ok(())
+    process_metadata_fid: F,
+) -> Result<ControlFlow<B>, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<B>,
+{
+    read_do(fd, process_metadata_fid)
 }
 
 /// Writes up to count bytes from the buffer starting at buf
@@ -500,7 +565,7 @@ pub fn read_with_fid_do(
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`].
 /// * `response` - This is a struct of type [`fanotify_response`]
-///                 that specifies how to deal with the request.
+///   that specifies how to deal with the request.
 ///
 /// # Example
 /// ```rust
@@ -524,15 +589,10 @@ pub fn read_with_fid_do(
 ///         if events.len() > 1 {
 ///             for event in events {
 ///                 println!("{event:#?}");
-///                 write(
-///                     fd,
-///                     &fanotify_response {
-///                         fd: event.fd,
-///                         // Allowig all events
-///                         response: FAN_ALLOW,
-///                     },
-///                 )
-///                 .unwrap();
+///                 if let Some(event_fd) = event.fd() {
+///                     // Allowig all events
+///                     write(fd, &fanotify_response::new(event_fd, FAN_ALLOW)).unwrap();
+///                 }
 ///             }
 ///         }
 ///     }
@@ -544,20 +604,186 @@ pub fn read_with_fid_do(
 /// }
 /// ```
 pub fn write(fd: &Fd, response: &fanotify_response) -> Result<isize, FanotifyError> {
-    unsafe {
-        match libc::write(
-            fd.as_raw_fd(),
-            response as *const fanotify_response as *const libc::c_void,
+    write_checked(fd, response, false)
+}
+
+/// Same as [`write()`], `audit_checked` is set once [`FAN_AUDIT`] was
+/// checked against the flags of the group.
+pub(crate) fn write_checked(
+    fd: &Fd,
+    response: &fanotify_response,
+    audit_checked: bool,
+) -> Result<isize, FanotifyError> {
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            response as *const fanotify_response as *const u8,
             FAN_WRITE_RESPONSE_LEN,
-        ) {
-            -1 => Err(FanotifyError::Write(
-                Error::last_os_error().raw_os_error().unwrap_or_default(),
-            )),
-            bytes => Ok(bytes),
+        )
+    };
+    write_response(fd, bytes, response.response, audit_checked)
+}
+
+/// Same as [`write()`] with the information records in `info`
+/// (Eg: a [`fanotify_response_info_audit_rule`]) written after the response.
+///
+/// `info` must be a sequence of whole records, each starting with a
+/// [`fanotify_response_info_header`] holding its length, and the response
+/// must have the [`FAN_INFO`] bit set if and only if `info` is not empty.
+/// Otherwise nothing is written and [`FanotifyError::InvalidResponse`]
+/// is returned.
+///
+/// Kernels older than 6.3 reject [`FAN_INFO`] with `EINVAL`, that is reported
+/// as [`FanotifyError::Unsupported`]. The same goes for an errno set with
+/// [`fan_deny_errno()`] on kernels older than 6.14 or in a group that is not
+/// [`FAN_CLASS_PRE_CONTENT`]. If [`FAN_AUDIT`] is set too, the `EINVAL` may
+/// come from a group initialised without [`FAN_ENABLE_AUDIT`] and is
+/// reported as [`FanotifyError::Write`], [`Fanotify::respond_with()`] checks
+/// the group first.
+pub fn write_with_info(
+    fd: &Fd,
+    response: &fanotify_response,
+    info: &[u8],
+) -> Result<isize, FanotifyError> {
+    write_with_info_checked(fd, response, info, false)
+}
+
+/// Same as [`write_with_info()`], `audit_checked` is set once
+/// [`FAN_AUDIT`] was checked against the flags of the group.
+pub(crate) fn write_with_info_checked(
+    fd: &Fd,
+    response: &fanotify_response,
+    info: &[u8],
+    audit_checked: bool,
+) -> Result<isize, FanotifyError> {
+    if (response.response & FAN_INFO != 0) == info.is_empty() {
+        return Err(FanotifyError::InvalidResponse(
+            "FAN_INFO must be set exactly when information records follow the response",
+        ));
+    }
+    let mut offset = 0;
+    while offset < info.len() {
+        let len = info
+            .get(offset + 2..offset + 4)
+            .map(|len| u16::from_ne_bytes([len[0], len[1]]) as usize)
+            .unwrap_or_default();
+        if len < mem::size_of::<fanotify_response_info_header>() || offset + len > info.len() {
+            return Err(FanotifyError::InvalidResponse(
+                "information record length doesn't match its size",
+            ));
         }
+        offset += len;
+    }
+    let mut bytes = Vec::with_capacity(FAN_WRITE_RESPONSE_LEN + info.len());
+    bytes.extend_from_slice(&response.fd.to_ne_bytes());
+    bytes.extend_from_slice(&response.response.to_ne_bytes());
+    bytes.extend_from_slice(info);
+    write_response(fd, &bytes, response.response, audit_checked)
+}
+
+/// Write the encoded response in one call, mapping `EINVAL` caused by
+/// newer response bits to [`FanotifyError::Unsupported`]. Unless
+/// `audit_checked`, an `EINVAL` with [`FAN_AUDIT`] set is left as is.
+fn write_response(
+    fd: &Fd,
+    bytes: &[u8],
+    response: u32,
+    audit_checked: bool,
+) -> Result<isize, FanotifyError> {
+    let written =
+        unsafe { libc::write(fd.as_raw_fd(), bytes.as_ptr() as *const c_void, bytes.len()) };
+    match written {
+        -1 => match Errno::last() {
+            Errno::EINVAL if response & FAN_AUDIT != 0 && !audit_checked => {
+                Err(FanotifyError::Write(Errno::EINVAL))
+            }
+            Errno::EINVAL if response & FAN_INFO != 0 => {
+                Err(FanotifyError::Unsupported("FAN_INFO"))
+            }
+            Errno::EINVAL if response >> FAN_ERRNO_SHIFT != 0 => {
+                Err(FanotifyError::Unsupported("FAN_DENY_ERRNO"))
+            }
+            errno => Err(FanotifyError::Write(errno)),
+        },
+        written if written as usize != bytes.len() => Err(FanotifyError::InvalidResponse(
+            "kernel accepted only part of the response",
+        )),
+        written => Ok(written),
     }
 }
 
+/// Reads whatever is pending on `fd` into `buff`, replacing its content.
+/// Reads at most `buff.capacity()` bytes, never reallocates.
+pub(crate) fn read_buffer(fd: &Fd, buff: &mut Vec<u8>) -> Result<(), FanotifyError> {
+    let len = buff.capacity();
+    read_buffer_len(fd, buff, len)
+}
+
+/// Reads the pending events that fit in `max_len` bytes into `buff`,
+/// leaves it empty if nothing is pending. `EAGAIN` and `EINTR` never
+/// fail the read, see [`try_read()`].
+pub(crate) fn try_read_buffer(
+    fd: &Fd,
+    buff: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<(), FanotifyError> {
+    let mut len = 0;
+    loop {
+        let pending = pending_len(fd)?;
+        if pending == 0 {
+            buff.clear();
+            return Ok(());
+        }
+        len = pending.max(len).min(max_len);
+        buff.clear();
+        buff.reserve(len);
+        match read_buffer_len(fd, buff, len) {
+            Ok(()) => return Ok(()),
+            Err(e) => match e.errno() {
+                // Another reader emptied the queue first.
+                Some(Errno::EAGAIN) => return Ok(()),
+                Some(Errno::EINTR) => continue,
+                // The first event has information records past `len`,
+                // kernel keeps it queued.
+                Some(Errno::EINVAL) if len < max_len => len *= 2,
+                _ => return Err(e),
+            },
+        }
+    }
+}
+
+/// Number of bytes of the pending events as reported by `FIONREAD`.
+fn pending_len(fd: &Fd) -> Result<usize, FanotifyError> {
+    let mut pending: libc::c_int = 0;
+    unsafe {
+        match libc::ioctl(fd.as_raw_fd(), libc::FIONREAD, &mut pending) {
+            0 => Ok(pending as usize),
+            _ => Err(FanotifyError::Read(Errno::last())),
+        }
+    }
+}
+
+/// Reads at most `len` bytes of pending events into `buff`,
+/// replacing its content. `len` must not exceed `buff.capacity()`.
+fn read_buffer_len(fd: &Fd, buff: &mut Vec<u8>, len: usize) -> Result<(), FanotifyError> {
+    debug_assert!(len <= buff.capacity());
+    buff.clear();
+    let sizeof;
+    unsafe {
+        // `libc::read()` is unsafe
+        sizeof = libc::read(fd.as_raw_fd(), buff.as_mut_ptr() as *mut c_void, len);
+    }
+
+    if sizeof == -1 {
+        return Err(FanotifyError::Read(Errno::last()));
+    }
+
+    unsafe {
+        // Vec.set_len() is unsafe operation. Kernel never writes past `len`.
+        buff.set_len(sizeof as usize);
+    }
+    Ok(())
+}
+
 /// Closes the file descriptor returned by [`init()`] or [`read()`]
 ///
 /// # Argument
@@ -566,9 +792,7 @@ pub fn close(fd: std::os::fd::RawFd) -> Result<(), FanotifyError> {
     unsafe {
         match libc::close(fd) {
             0 => Ok(()),
-            _ => Err(FanotifyError::Close(
-                Error::last_os_error().raw_os_error().unwrap_or_default(),
-            )),
+            _ => Err(FanotifyError::Close(Errno::last())),
         }
     }
 }
@@ -1,11 +1,43 @@
 //! Defines all the Error that can be generated by `fanotify`
 //! at runtime. All errors comes with proper detailed description.
+//!
+//! [`FanotifyError`] records the failed operation along with the flags,
+//! mask or path passed to it and the [`Errno`] the kernel returned. Its
+//! `Display` is a single line, the long description of the errno for the
+//! operation is available from [`FanotifyError::description()`].
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::errors::*;
+//! # use naughtyfy::mask::*;
+//! let err = FanotifyError::Mark {
+//!     errno: Errno::EINVAL,
+//!     flags: MarkFlags::ADD | MarkFlags::MOUNT,
+//!     mask: EventMask::CREATE,
+//!     path: Some("/tmp".into()),
+//! };
+//! assert_eq!(
+//!     err.to_string(),
+//!     "fanotify_mark(flags=ADD|MOUNT, mask=CREATE, path=\"/tmp\") failed: EINVAL (Invalid argument)"
+//! );
+//! assert!(!err.is_retryable());
+//! let io: std::io::Error = err.into();
+//! assert_eq!(io.kind(), std::io::ErrorKind::InvalidInput);
+//! assert_eq!(io.raw_os_error(), Some(Errno::EINVAL.raw()));
+//! ```
 
-use std::error::Error;
-use std::fmt::{self, Debug};
+use crate::{
+    mask::{EventFFlags, EventMask, InitFlags, MarkFlags},
+    validate::Violation,
+};
+use std::{error::Error, ffi::OsString, fmt, io, path::PathBuf};
 
 #[allow(unused_imports)]
 use crate::api::*;
+#[allow(unused_imports)]
+use crate::fid::FidResolver;
+#[allow(unused_imports)]
+use crate::flags::FANOTIFY_METADATA_VERSION;
 
 /// Matches description with errno recieved after calling
 /// [`init()`]
@@ -229,117 +261,446 @@ fn close_code_desc(code: i32) -> String {
     }
 }
 
+/// Matches description with errno recieved while resolving a file handle
+/// with [`FidResolver`]
+#[inline]
+fn resolve_code_desc(code: i32) -> String {
+    match code {
+        libc::ESTALE => "The file handle is stale, the object it referred to
+                has been deleted since the event was generated."
+            .to_string(),
+        libc::ENODEV => "No mount of the filesystem with the fsid of the
+                event is visible in /proc/self/mountinfo."
+            .to_string(),
+        libc::EPERM => "The caller lacks the CAP_DAC_READ_SEARCH capability
+                needed for open_by_handle_at()."
+            .to_string(),
+        libc::EINVAL => "The file handle is malformed or the filesystem doesn't
+                support decoding file handles."
+            .to_string(),
+        libc::ELOOP => "The handle refers to a symbolic link, but O_PATH was not
+                specified in flags."
+            .to_string(),
+        libc::EMFILE => "The per-process limit on the number of open file
+                descriptors has been reached."
+            .to_string(),
+        libc::ESRCH => "The process referred to by the pidfd has exited
+                and was reaped."
+            .to_string(),
+        _ => "Unnown error occured.".to_string(),
+    }
+}
+
+/// Error number returned by a failed system call.
+///
+/// Variants are named after the C constants, the ones fanotify calls
+/// are documented to return. Any other value is kept in [`Errno::Other`].
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Errno {
+    EPERM,
+    ENOENT,
+    ESRCH,
+    EINTR,
+    EIO,
+    EBADF,
+    EAGAIN,
+    ENOMEM,
+    EACCES,
+    EFAULT,
+    EBUSY,
+    EEXIST,
+    EXDEV,
+    ENODEV,
+    ENOTDIR,
+    EISDIR,
+    EINVAL,
+    ENFILE,
+    EMFILE,
+    ETXTBSY,
+    EFBIG,
+    ENOSPC,
+    EPIPE,
+    ENOSYS,
+    ELOOP,
+    ENAMETOOLONG,
+    EOPNOTSUPP,
+    EOVERFLOW,
+    ESTALE,
+    EDQUOT,
+    EDESTADDRREQ,
+    /// Errno without a variant.
+    Other(i32),
+}
+
+/// Variants and their raw values, also used for names.
+const ERRNOS: [(Errno, i32, &str); 31] = [
+    (Errno::EPERM, libc::EPERM, "EPERM"),
+    (Errno::ENOENT, libc::ENOENT, "ENOENT"),
+    (Errno::ESRCH, libc::ESRCH, "ESRCH"),
+    (Errno::EINTR, libc::EINTR, "EINTR"),
+    (Errno::EIO, libc::EIO, "EIO"),
+    (Errno::EBADF, libc::EBADF, "EBADF"),
+    (Errno::EAGAIN, libc::EAGAIN, "EAGAIN"),
+    (Errno::ENOMEM, libc::ENOMEM, "ENOMEM"),
+    (Errno::EACCES, libc::EACCES, "EACCES"),
+    (Errno::EFAULT, libc::EFAULT, "EFAULT"),
+    (Errno::EBUSY, libc::EBUSY, "EBUSY"),
+    (Errno::EEXIST, libc::EEXIST, "EEXIST"),
+    (Errno::EXDEV, libc::EXDEV, "EXDEV"),
+    (Errno::ENODEV, libc::ENODEV, "ENODEV"),
+    (Errno::ENOTDIR, libc::ENOTDIR, "ENOTDIR"),
+    (Errno::EISDIR, libc::EISDIR, "EISDIR"),
+    (Errno::EINVAL, libc::EINVAL, "EINVAL"),
+    (Errno::ENFILE, libc::ENFILE, "ENFILE"),
+    (Errno::EMFILE, libc::EMFILE, "EMFILE"),
+    (Errno::ETXTBSY, libc::ETXTBSY, "ETXTBSY"),
+    (Errno::EFBIG, libc::EFBIG, "EFBIG"),
+    (Errno::ENOSPC, libc::ENOSPC, "ENOSPC"),
+    (Errno::EPIPE, libc::EPIPE, "EPIPE"),
+    (Errno::ENOSYS, libc::ENOSYS, "ENOSYS"),
+    (Errno::ELOOP, libc::ELOOP, "ELOOP"),
+    (Errno::ENAMETOOLONG, libc::ENAMETOOLONG, "ENAMETOOLONG"),
+    (Errno::EOPNOTSUPP, libc::EOPNOTSUPP, "EOPNOTSUPP"),
+    (Errno::EOVERFLOW, libc::EOVERFLOW, "EOVERFLOW"),
+    (Errno::ESTALE, libc::ESTALE, "ESTALE"),
+    (Errno::EDQUOT, libc::EDQUOT, "EDQUOT"),
+    (Errno::EDESTADDRREQ, libc::EDESTADDRREQ, "EDESTADDRREQ"),
+];
+
+impl Errno {
+    /// Typed `errno`.
+    pub fn from_raw(errno: i32) -> Self {
+        ERRNOS
+            .iter()
+            .find(|(_, raw, _)| *raw == errno)
+            .map_or(Errno::Other(errno), |(errno, _, _)| *errno)
+    }
+
+    /// Errno of the last failed call of this thread.
+    pub fn last() -> Self {
+        Self::from_raw(
+            io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Raw value of the errno.
+    pub fn raw(self) -> i32 {
+        match self {
+            Errno::Other(errno) => errno,
+            _ => ERRNOS
+                .iter()
+                .find(|(errno, _, _)| *errno == self)
+                .map_or(0, |(_, raw, _)| *raw),
+        }
+    }
+
+    /// Message of strerror(3) for the errno.
+    pub fn message(self) -> String {
+        let message = io::Error::from_raw_os_error(self.raw()).to_string();
+        match message.find(" (os error") {
+            Some(end) => message[..end].to_string(),
+            None => message,
+        }
+    }
+}
+
+impl From<i32> for Errno {
+    fn from(errno: i32) -> Self {
+        Self::from_raw(errno)
+    }
+}
+
+/// Errno carried by an [`io::Error`], `EIO` if there is none.
+impl From<&io::Error> for Errno {
+    fn from(err: &io::Error) -> Self {
+        Self::from_raw(err.raw_os_error().unwrap_or(libc::EIO))
+    }
+}
+
+impl From<Errno> for io::Error {
+    fn from(errno: Errno) -> Self {
+        io::Error::from_raw_os_error(errno.raw())
+    }
+}
+
+/// Name of the C constant, or the number for [`Errno::Other`].
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match ERRNOS.iter().find(|(errno, _, _)| errno == self) {
+            Some((_, _, name)) => f.write_str(name),
+            None => write!(f, "errno {}", self.raw()),
+        }
+    }
+}
+
+impl Error for Errno {}
+
+/// Operation an error comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// [`init()`]
+    Init,
+    /// [`mark()`]
+    Mark,
+    /// [`read()`]
+    Read,
+    /// [`write()`]
+    Write,
+    /// [`close()`]
+    Close,
+    /// Decoding events read into the buffer
+    Decode,
+    /// Opening a file handle with [`FidResolver`]
+    Resolve,
+    /// Checking flags before a call, see [`validate`](crate::validate)
+    Validate,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Init => "fanotify_init",
+            Operation::Mark => "fanotify_mark",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Close => "close",
+            Operation::Decode => "decode",
+            Operation::Resolve => "open_by_handle_at",
+            Operation::Validate => "validate",
+        })
+    }
+}
+
 /// Error type for all fanotify errors that can occure at runtime. <br>
-/// This can of 5 types <br>
+/// This can of 12 types <br>
 /// * [`FanotifyError::Init`]
 /// * [`FanotifyError::Mark`]
 /// * [`FanotifyError::Read`]
 /// * [`FanotifyError::Write`]
 /// * [`FanotifyError::Close`]
+/// * [`FanotifyError::Version`]
+/// * [`FanotifyError::Malformed`]
+/// * [`FanotifyError::Resolve`]
+/// * [`FanotifyError::Unsupported`]
+/// * [`FanotifyError::InvalidResponse`]
+/// * [`FanotifyError::InvalidPath`]
+/// * [`FanotifyError::Invalid`]
+///
+/// Converts into [`io::Error`] with the matching [`io::ErrorKind`]. An
+/// error with an errno becomes [`io::Error::from_raw_os_error()`] so
+/// `raw_os_error()` still returns it, the others are kept as inner error.
+#[derive(Debug)]
 pub enum FanotifyError {
     /// Error produced by [`init()`]
-    Init(i32),
-    /// Error produced by [`mark()`]
-    Mark(i32),
+    Init {
+        errno: Errno,
+        flags: InitFlags,
+        event_f_flags: EventFFlags,
+    },
+    /// Error produced by [`mark()`], `path` is `None` when the object
+    /// was given by fd
+    Mark {
+        errno: Errno,
+        flags: MarkFlags,
+        mask: EventMask,
+        path: Option<PathBuf>,
+    },
     /// Error produced by [`read()`]
-    Read(i32),
+    Read(Errno),
     /// Error produced by [`write()`]
-    Write(i32),
+    Write(Errno),
     /// Error produced by [`close()`]
-    Close(i32),
+    Close(Errno),
+    /// Event read from the buffer has a metadata version other than
+    /// [`FANOTIFY_METADATA_VERSION`]
+    Version(u8),
+    /// Event record at the given byte offset of the read buffer
+    /// has lengths that don't fit in the buffer
+    Malformed(usize),
+    /// Error produced by [`FidResolver`] or while looking up the
+    /// process of a pidfd
+    Resolve(Errno),
+    /// Kernel rejected a feature with `EINVAL` because it predates it,
+    /// holds the name of the feature (Eg: `"FAN_INFO"`)
+    Unsupported(&'static str),
+    /// Response was rejected before being written to the kernel,
+    /// holds the reason
+    InvalidResponse(&'static str),
+    /// Path holds a NUL byte and can't be passed to the kernel
+    InvalidPath(OsString),
+    /// Flags break a rule of fanotify_init(2) or fanotify_mark(2),
+    /// found before calling the kernel
+    Invalid(Violation),
 }
-impl Error for FanotifyError {}
 
-impl fmt::Display for FanotifyError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl FanotifyError {
+    /// Operation that failed.
+    pub fn operation(&self) -> Operation {
         match self {
-            Self::Init(code) => {
-                write!(
-                    f,
-                    "FanotifyInitError:\nCode: {}\nDesciption: {}",
-                    code,
-                    init_code_desc(*code)
-                )
-            }
-            Self::Mark(code) => {
-                write!(
-                    f,
-                    "FanotifyMarkError:\nCode: {}\nDesciption: {}",
-                    code,
-                    mark_code_desc(*code)
-                )
-            }
-            Self::Read(code) => {
-                write!(
-                    f,
-                    "FanotifyReadError:\nCode: {}\nDesciption: {}",
-                    code,
-                    read_code_desc(*code)
-                )
-            }
-            Self::Write(code) => {
-                write!(
-                    f,
-                    "FanotifyWriteError:\nCode: {}\nDesciption: {}",
-                    code,
-                    write_code_desc(*code)
-                )
+            Self::Init { .. } => Operation::Init,
+            Self::Mark { .. } | Self::InvalidPath(_) => Operation::Mark,
+            Self::Read(_) => Operation::Read,
+            Self::Write(_) | Self::Unsupported(_) | Self::InvalidResponse(_) => Operation::Write,
+            Self::Close(_) => Operation::Close,
+            Self::Version(_) | Self::Malformed(_) => Operation::Decode,
+            Self::Resolve(_) => Operation::Resolve,
+            Self::Invalid(_) => Operation::Validate,
+        }
+    }
+
+    /// Errno returned by the kernel, `None` for errors found by this library.
+    pub fn errno(&self) -> Option<Errno> {
+        match self {
+            Self::Init { errno, .. }
+            | Self::Mark { errno, .. }
+            | Self::Read(errno)
+            | Self::Write(errno)
+            | Self::Close(errno)
+            | Self::Resolve(errno) => Some(*errno),
+            _ => None,
+        }
+    }
+
+    /// Raw errno returned by the kernel.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.errno().map(Errno::raw)
+    }
+
+    /// [`io::ErrorKind`] matching the error.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::Version(_) | Self::Malformed(_) => io::ErrorKind::InvalidData,
+            Self::Unsupported(_) => io::ErrorKind::Unsupported,
+            Self::InvalidResponse(_) | Self::InvalidPath(_) | Self::Invalid(_) => {
+                io::ErrorKind::InvalidInput
             }
-            Self::Close(code) => {
-                write!(
-                    f,
-                    "FanotifyCloseError:\nCode: {}\nDesciption: {}",
-                    code,
-                    close_code_desc(*code)
-                )
+            _ => self
+                .errno()
+                .map_or(io::ErrorKind::Other, |errno| io::Error::from(errno).kind()),
+        }
+    }
+
+    /// `true` if the caller lacks a capability or access to the object.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(self.errno(), Some(Errno::EPERM | Errno::EACCES))
+    }
+
+    /// `true` if the running kernel or filesystem doesn't support
+    /// what was asked.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::Unsupported(_))
+            || matches!(
+                self.errno(),
+                Some(Errno::ENOSYS | Errno::EOPNOTSUPP | Errno::EXDEV)
+            )
+    }
+
+    /// `true` if the same call may succeed when made again
+    /// (Eg: `EAGAIN` of a nonblocking read).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.errno(), Some(Errno::EAGAIN | Errno::EINTR))
+    }
+
+    /// Long description of the error, what the errno means for the operation.
+    pub fn description(&self) -> String {
+        match self {
+            Self::Init { errno, .. } => init_code_desc(errno.raw()),
+            Self::Mark { errno, .. } => mark_code_desc(errno.raw()),
+            Self::Read(errno) => read_code_desc(errno.raw()),
+            Self::Write(errno) => write_code_desc(errno.raw()),
+            Self::Close(errno) => close_code_desc(errno.raw()),
+            Self::Resolve(errno) => resolve_code_desc(errno.raw()),
+            Self::Version(vers) => format!(
+                "Kernel reported event metadata version {} but this library \
+                understands version {}, the fanotify file descriptor should not \
+                be used any further.",
+                vers, FANOTIFY_METADATA_VERSION
+            ),
+            Self::Malformed(_) => "Event record length doesn't fit in the read buffer.".to_string(),
+            Self::Unsupported(feature) => format!("{} is not supported by this kernel.", feature),
+            Self::InvalidResponse(reason) => reason.to_string(),
+            Self::InvalidPath(_) => {
+                "Path contains a NUL byte and can't be passed to the kernel.".to_string()
             }
+            Self::Invalid(violation) => violation.to_string(),
         }
     }
 }
 
-impl Debug for FanotifyError {
+impl Error for FanotifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Init { errno, .. }
+            | Self::Mark { errno, .. }
+            | Self::Read(errno)
+            | Self::Write(errno)
+            | Self::Close(errno)
+            | Self::Resolve(errno) => Some(errno),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FanotifyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Init(code) => {
-                write!(
-                    f,
-                    "FanotifyInitError:\nCode: {}\nDesciption: {}",
-                    code,
-                    init_code_desc(*code)
-                )
+            Self::Init {
+                flags,
+                event_f_flags,
+                ..
+            } => write!(
+                f,
+                "{}(flags={}, event_f_flags={})",
+                self.operation(),
+                flags,
+                event_f_flags
+            )?,
+            Self::Mark {
+                flags, mask, path, ..
+            } => {
+                write!(f, "{}(flags={}, mask={}", self.operation(), flags, mask)?;
+                if let Some(path) = path {
+                    write!(f, ", path={:?}", path)?;
+                }
+                f.write_str(")")?;
             }
-            Self::Mark(code) => {
-                write!(
+            Self::Version(vers) => {
+                return write!(
                     f,
-                    "FanotifyMarkError:\nCode: {}\nDesciption: {}",
-                    code,
-                    mark_code_desc(*code)
+                    "event metadata version {} is not supported, expected {}",
+                    vers, FANOTIFY_METADATA_VERSION
                 )
             }
-            Self::Read(code) => {
-                write!(
-                    f,
-                    "FanotifyReadError:\nCode: {}\nDesciption: {}",
-                    code,
-                    read_code_desc(*code)
-                )
+            Self::Malformed(offset) => {
+                return write!(f, "malformed event record at offset {}", offset)
             }
-            Self::Write(code) => {
-                write!(
-                    f,
-                    "FanotifyWriteError:\nCode: {}\nDesciption: {}",
-                    code,
-                    write_code_desc(*code)
-                )
+            Self::Unsupported(feature) => {
+                return write!(f, "{} is not supported by this kernel", feature)
             }
-            Self::Close(code) => {
-                write!(
-                    f,
-                    "FanotifyCloseError:\nCode: {}\nDesciption: {}",
-                    code,
-                    close_code_desc(*code)
-                )
+            Self::InvalidResponse(reason) => return write!(f, "invalid response: {}", reason),
+            Self::InvalidPath(path) => {
+                return write!(f, "invalid path {:?}: contains a NUL byte", path)
             }
+            Self::Invalid(violation) => {
+                return write!(f, "invalid flags: {} {}", violation.flag, violation.rule)
+            }
+            _ => write!(f, "{}", self.operation())?,
+        }
+        match self.errno() {
+            Some(errno) => write!(f, " failed: {} ({})", errno, errno.message()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<FanotifyError> for io::Error {
+    fn from(err: FanotifyError) -> Self {
+        match err.raw_os_error() {
+            Some(raw) => io::Error::from_raw_os_error(raw),
+            None => io::Error::new(err.kind(), err),
         }
     }
 }
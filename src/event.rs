@@ -0,0 +1,1024 @@
+//! Decoding of the variable-length event records returned by
+//! [`read()`] and related read functions.
+//!
+//! The kernel does not hand out an array of fixed-size structures.
+//! Every record starts with a [`fanotify_event_metadata`] header, whose
+//! `event_len` field is the offset to the next record. Groups that were
+//! initialised with [`FAN_REPORT_FID`], [`FAN_REPORT_DFID_NAME`],
+//! [`FAN_REPORT_PIDFD`] etc. append one or more information records after
+//! the header, so records inside a single buffer may all have different
+//! lengths.
+
+use crate::{errors::*, flags::*, mask::*, types::*};
+use std::{
+    ffi::OsString,
+    mem,
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        unix::ffi::OsStringExt,
+    },
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{api::*, group::Fanotify};
+
+/// Size of [`fanotify_event_metadata`] as laid out by the kernel.
+pub(crate) const FAN_EVENT_METADATA_LEN: usize = mem::size_of::<fanotify_event_metadata>();
+
+/// Size of [`fanotify_event_info_header`].
+const FAN_INFO_HEADER_LEN: usize = mem::size_of::<fanotify_event_info_header>();
+
+/// Size of the fixed part of [`fanotify_event_info_fid`] (header and fsid).
+const FAN_INFO_FID_LEN: usize = mem::size_of::<fanotify_event_info_fid>();
+
+/// Size of the fixed part of [`file_handle`].
+const FILE_HANDLE_LEN: usize = mem::size_of::<file_handle>();
+
+/// A single event decoded from the read buffer.
+///
+/// Owns the event fd (if any), which is closed when the event is dropped.
+/// Use [`Event::take_fd()`] to keep the file open past the event.
+#[derive(Debug)]
+pub struct Event {
+    /// Bit mask describing the event, see [`fanotify_event_metadata::mask`].
+    pub mask: u64,
+    /// PID (or TID with [`FAN_REPORT_TID`]) of the process that caused the event.
+    pub pid: i32,
+    /// Open fd of the object, `None` for [`FAN_NOFD`].
+    fd: Option<OwnedFd>,
+    /// Information records between the end of metadata and `event_len`.
+    /// Empty unless the group reports additional information records.
+    pub info: Vec<InfoRecord>,
+}
+
+impl Event {
+    /// Fd of the object being accessed.
+    ///
+    /// `None` for queue overflow, for groups that identify objects
+    /// by file handles and after [`Event::take_fd()`].
+    #[inline]
+    pub fn fd(&self) -> Option<&OwnedFd> {
+        self.fd.as_ref()
+    }
+
+    /// Take ownership of the event fd, it won't be closed when
+    /// the event is dropped.
+    #[inline]
+    pub fn take_fd(&mut self) -> Option<OwnedFd> {
+        self.fd.take()
+    }
+
+    /// Typed [`mask`](Self::mask).
+    #[inline]
+    pub fn event_mask(&self) -> EventMask {
+        EventMask::from(self.mask)
+    }
+
+    /// What happened, one kind per event bit set in the mask.
+    /// The kernel merges events on the same object into one record,
+    /// so a single event may yield several kinds.
+    #[inline]
+    pub fn kinds(&self) -> EventKinds {
+        EventKinds::new(self.mask)
+    }
+
+    /// `true` if the object is a directory ([`FAN_ONDIR`]).
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.mask & FAN_ONDIR != 0
+    }
+
+    /// PID of the process that caused the event, or its TID if the
+    /// group was initialised with [`FAN_REPORT_TID`].
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Decoded information records of the event.
+    #[inline]
+    pub fn info(&self) -> &[InfoRecord] {
+        &self.info
+    }
+
+    /// Pidfd of the process that caused the event if group was
+    /// initialised with [`FAN_REPORT_PIDFD`], `None` if kernel
+    /// couldn't create one.
+    pub fn pidfd(&self) -> Option<&OwnedFd> {
+        self.pidfd_record()?.fd()
+    }
+
+    /// Pidfd record of the event, telling why [`pidfd()`](Self::pidfd)
+    /// is missing. `None` unless the group reports pidfds.
+    pub fn pidfd_record(&self) -> Option<&PidfdRecord> {
+        self.info.iter().find_map(|record| match record {
+            InfoRecord::Pidfd(pidfd) => Some(pidfd),
+            _ => None,
+        })
+    }
+
+    /// Error and affected inode of a [`FAN_FS_ERROR`] event, `None` for
+    /// other events.
+    pub fn fs_error(&self) -> Option<FsError> {
+        if self.mask & FAN_FS_ERROR == 0 {
+            return None;
+        }
+        let mut fid = None;
+        let mut error = None;
+        for record in &self.info {
+            match record {
+                InfoRecord::Fid(record) => fid = Some(record),
+                InfoRecord::Error {
+                    error: errno,
+                    error_count,
+                } => error = Some((*errno, *error_count)),
+                _ => {}
+            }
+        }
+        let fid = fid?;
+        let (errno, error_count) = error?;
+        Some(FsError {
+            fsid: fid.fsid,
+            error: Errno::from(errno),
+            error_count,
+            // Errors not tied to an inode come with an empty handle.
+            inode: (!fid.handle.bytes.is_empty()).then(|| fid.clone()),
+        })
+    }
+
+    /// Both locations of a [`FAN_RENAME`] event, `None` for other events
+    /// or if the group doesn't report [`FAN_REPORT_DFID_NAME`].
+    pub fn rename(&self) -> Option<Rename> {
+        if self.mask & FAN_RENAME == 0 {
+            return None;
+        }
+        Rename::from_info(&self.info)
+    }
+}
+
+/// A [`FAN_FS_ERROR`] event, see [`Event::fs_error()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsError {
+    /// Filesystem that reported the error.
+    pub fsid: __kernel_fsid_t,
+    /// The first error since the last event was read (Eg: `EUCLEAN`
+    /// for a corrupted filesystem).
+    pub error: Errno,
+    /// Number of errors since the last event was read.
+    pub error_count: u32,
+    /// The inode the error is about, `None` for errors of the
+    /// filesystem as a whole.
+    pub inode: Option<FidRecord>,
+}
+
+/// A directory entry, the directory identified by its file handle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameEntry {
+    /// The directory, its [`FidRecord::name`] is `None`.
+    pub dir: FidRecord,
+    /// Name of the entry in the directory.
+    pub name: OsString,
+    /// Path of the entry, set by [`FidResolver::resolve_rename()`](crate::fid::FidResolver::resolve_rename)
+    /// if the directory can be opened.
+    pub path: Option<std::path::PathBuf>,
+}
+
+/// A [`FAN_RENAME`] event with both locations, see [`Event::rename()`].
+///
+/// One event is reported for the whole move, whether the entry stays in
+/// its directory or not, so it can be applied as a move rather than as
+/// a delete and a create. The kernel reports a location only if the group
+/// watches its directory, an entry moved out of the watched directories
+/// has no [`to`](Self::to) and one moved in has no [`from`](Self::from).
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::fid::*;
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// let dir = std::env::temp_dir().join("naughtyfy-rename-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(dir.join("sub")).unwrap();
+/// std::fs::write(dir.join("old"), "").unwrap();
+/// let flags = InitFlags::CLASS_NOTIF | InitFlags::NONBLOCK | InitFlags::REPORT_DFID_NAME_TARGET;
+/// match Fanotify::with_flags(flags, EventFFlags::RDONLY) {
+///     Ok(mut group) => {
+///         let mask = EventMask::RENAME | EventMask::EVENT_ON_CHILD;
+///         group.mark_target(MarkFlags::empty(), mask, MarkTarget::inode(dir.as_path())).unwrap();
+///         group.mark_target(MarkFlags::empty(), mask, MarkTarget::inode(dir.join("sub").as_path())).unwrap();
+///         std::fs::rename(dir.join("old"), dir.join("sub/new")).unwrap();
+///
+///         let events = group.read_events().unwrap();
+///         let mut rename = events[0].rename().unwrap();
+///         assert_eq!(rename.from.as_ref().unwrap().name, "old");
+///         assert_eq!(rename.to.as_ref().unwrap().name, "new");
+///         assert!(rename.target.is_some());
+///         FidResolver::new().resolve_rename(&mut rename);
+///         // Opening handles needs CAP_DAC_READ_SEARCH.
+///         if let Some(path) = &rename.to.unwrap().path {
+///             assert_eq!(path, &dir.join("sub/new"));
+///         }
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    /// Where the entry was, from [`InfoRecord::OldDfidName`].
+    pub from: Option<RenameEntry>,
+    /// Where the entry is now, from [`InfoRecord::NewDfidName`].
+    pub to: Option<RenameEntry>,
+    /// The renamed object itself if the group reports [`FAN_REPORT_FID`]
+    /// (Eg: [`FAN_REPORT_DFID_NAME_TARGET`]).
+    pub target: Option<FidRecord>,
+}
+
+impl Rename {
+    /// Build a rename from the information records of a [`FAN_RENAME`] event,
+    /// `None` if neither location is reported.
+    pub fn from_info(info: &[InfoRecord]) -> Option<Self> {
+        let entry = |fid: &FidRecord| RenameEntry {
+            dir: FidRecord {
+                name: None,
+                ..fid.clone()
+            },
+            name: fid.name.clone().unwrap_or_default(),
+            path: None,
+        };
+        let mut from = None;
+        let mut to = None;
+        let mut target = None;
+        for record in info {
+            match record {
+                InfoRecord::OldDfidName(fid) => from = Some(entry(fid)),
+                InfoRecord::NewDfidName(fid) => to = Some(entry(fid)),
+                InfoRecord::Fid(fid) => target = Some(fid.clone()),
+                _ => {}
+            }
+        }
+        if from.is_none() && to.is_none() {
+            return None;
+        }
+        Some(Rename { from, to, target })
+    }
+}
+
+/// A single event bit of [`fanotify_event_metadata::mask`].
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::event::*;
+/// # use naughtyfy::flags::*;
+/// let kinds: Vec<_> = EventKinds::new(FAN_CREATE | FAN_CLOSE_WRITE | FAN_ONDIR).collect();
+/// assert_eq!(kinds, [EventKind::CloseWrite, EventKind::Create]);
+/// assert_eq!(EventKind::OpenPerm.mask(), FAN_OPEN_PERM);
+/// assert!(EventKind::OpenPerm.is_permission());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// [`FAN_ACCESS`], a file was read.
+    Access,
+    /// [`FAN_MODIFY`], a file was written.
+    Modify,
+    /// [`FAN_ATTRIB`], metadata of the object changed.
+    Attrib,
+    /// [`FAN_CLOSE_WRITE`], a file opened for writing was closed.
+    CloseWrite,
+    /// [`FAN_CLOSE_NOWRITE`], a file opened read only was closed.
+    CloseNoWrite,
+    /// [`FAN_OPEN`], a file or directory was opened.
+    Open,
+    /// [`FAN_MOVED_FROM`], an entry was moved out of the directory.
+    MovedFrom,
+    /// [`FAN_MOVED_TO`], an entry was moved into the directory.
+    MovedTo,
+    /// [`FAN_CREATE`], an entry was created in the directory.
+    Create,
+    /// [`FAN_DELETE`], an entry was deleted from the directory.
+    Delete,
+    /// [`FAN_DELETE_SELF`], the marked object was deleted.
+    DeleteSelf,
+    /// [`FAN_MOVE_SELF`], the marked object was moved.
+    MoveSelf,
+    /// [`FAN_OPEN_EXEC`], a file was opened to be executed.
+    OpenExec,
+    /// [`FAN_Q_OVERFLOW`], events were lost.
+    Overflow,
+    /// [`FAN_FS_ERROR`], the filesystem reported an error.
+    FsError,
+    /// [`FAN_OPEN_PERM`], permission to open is requested.
+    OpenPerm,
+    /// [`FAN_ACCESS_PERM`], permission to read is requested.
+    AccessPerm,
+    /// [`FAN_OPEN_EXEC_PERM`], permission to open for execution is requested.
+    OpenExecPerm,
+    /// [`FAN_RENAME`], an entry was renamed, see [`InfoRecord::OldDfidName`].
+    Rename,
+}
+
+/// Every kind with its bit, ordered by bit.
+const EVENT_KINDS: [(u64, EventKind); 19] = [
+    (FAN_ACCESS, EventKind::Access),
+    (FAN_MODIFY, EventKind::Modify),
+    (FAN_ATTRIB, EventKind::Attrib),
+    (FAN_CLOSE_WRITE, EventKind::CloseWrite),
+    (FAN_CLOSE_NOWRITE, EventKind::CloseNoWrite),
+    (FAN_OPEN, EventKind::Open),
+    (FAN_MOVED_FROM, EventKind::MovedFrom),
+    (FAN_MOVED_TO, EventKind::MovedTo),
+    (FAN_CREATE, EventKind::Create),
+    (FAN_DELETE, EventKind::Delete),
+    (FAN_DELETE_SELF, EventKind::DeleteSelf),
+    (FAN_MOVE_SELF, EventKind::MoveSelf),
+    (FAN_OPEN_EXEC, EventKind::OpenExec),
+    (FAN_Q_OVERFLOW, EventKind::Overflow),
+    (FAN_FS_ERROR, EventKind::FsError),
+    (FAN_OPEN_PERM, EventKind::OpenPerm),
+    (FAN_ACCESS_PERM, EventKind::AccessPerm),
+    (FAN_OPEN_EXEC_PERM, EventKind::OpenExecPerm),
+    (FAN_RENAME, EventKind::Rename),
+];
+
+impl EventKind {
+    /// The bit of the kind.
+    pub fn mask(&self) -> u64 {
+        EVENT_KINDS
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .map_or(0, |(bit, _)| *bit)
+    }
+
+    /// `true` for the kinds that must be answered, see [`PermissionRequest`](crate::permission::PermissionRequest).
+    pub fn is_permission(&self) -> bool {
+        matches!(
+            self,
+            EventKind::OpenPerm | EventKind::AccessPerm | EventKind::OpenExecPerm
+        )
+    }
+}
+
+/// Iterator over the [`EventKind`]s of a mask, lowest bit first.
+/// Bits that are not events ([`FAN_ONDIR`]) or unknown are skipped.
+#[derive(Debug, Clone)]
+pub struct EventKinds {
+    mask: u64,
+    next: usize,
+}
+
+impl EventKinds {
+    /// Iterate over the kinds in `mask`.
+    pub fn new(mask: u64) -> Self {
+        EventKinds { mask, next: 0 }
+    }
+}
+
+impl Iterator for EventKinds {
+    type Item = EventKind;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((bit, kind)) = EVENT_KINDS.get(self.next) {
+            self.next += 1;
+            if self.mask & bit != 0 {
+                return Some(*kind);
+            }
+        }
+        None
+    }
+}
+
+/// Opaque file handle as returned by name_to_handle_at(2),
+/// can be passed to open_by_handle_at(2).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileHandle {
+    /// Filesystem specific type of the handle.
+    pub handle_type: i32,
+    /// The handle itself, `handle_bytes` long.
+    pub bytes: Vec<u8>,
+}
+
+/// Decoded form of [`fanotify_event_info_fid`] and its DFID variants.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FidRecord {
+    /// Filesystem the object lives on, same as `f_fsid` from statfs(2).
+    pub fsid: __kernel_fsid_t,
+    /// Handle of the object (or of its directory for the DFID variants).
+    pub handle: FileHandle,
+    /// Name of the directory entry, present only for the `*DfidName`
+    /// records. `"."` refers to the directory itself.
+    pub name: Option<OsString>,
+}
+
+/// Every information record kernel may attach to an event.
+#[derive(Debug)]
+pub enum InfoRecord {
+    /// [`FAN_EVENT_INFO_TYPE_FID`], identifies the object itself.
+    Fid(FidRecord),
+    /// [`FAN_EVENT_INFO_TYPE_DFID`], identifies the parent directory.
+    Dfid(FidRecord),
+    /// [`FAN_EVENT_INFO_TYPE_DFID_NAME`], parent directory and entry name.
+    DfidName(FidRecord),
+    /// [`FAN_EVENT_INFO_TYPE_NEW_DFID_NAME`], new location of a renamed entry.
+    NewDfidName(FidRecord),
+    /// [`FAN_EVENT_INFO_TYPE_OLD_DFID_NAME`], old location of a renamed entry.
+    OldDfidName(FidRecord),
+    /// [`FAN_EVENT_INFO_TYPE_PIDFD`], pidfd of the process that caused the event.
+    Pidfd(PidfdRecord),
+    /// [`FAN_EVENT_INFO_TYPE_ERROR`], attached to [`FAN_FS_ERROR`].
+    Error {
+        /// Errno of the first error.
+        error: i32,
+        /// Number of errors since the last event was read.
+        error_count: u32,
+    },
+    /// [`FAN_EVENT_INFO_TYPE_RANGE`], accessed range of the file.
+    Range { offset: u64, count: u64 },
+    /// [`FAN_EVENT_INFO_TYPE_MNT`], mount the event is about.
+    Mnt { mnt_id: u64 },
+    /// Record of a type this library doesn't know about.
+    Unknown {
+        info_type: u8,
+        /// Record bytes following the header.
+        data: Vec<u8>,
+    },
+}
+
+/// Pidfd record of a group initialised with [`FAN_REPORT_PIDFD`].
+///
+/// Unlike [`Event::pid`] a pidfd keeps referring to the same process after
+/// it exits, see [`process`](crate::process) for reading its details safely.
+#[derive(Debug)]
+pub enum PidfdRecord {
+    /// Pidfd of the process, closed when dropped.
+    Fd(OwnedFd),
+    /// [`FAN_NOPIDFD`], the process was gone before the event was read.
+    Exited,
+    /// [`FAN_EPIDFD`], kernel failed to create the pidfd.
+    Failed,
+}
+
+impl PidfdRecord {
+    /// Decode the `pidfd` field, taking ownership of the fd.
+    ///
+    /// # Safety
+    /// `fd` must be a pidfd handed out by the kernel and owned by no one else.
+    unsafe fn from_raw(fd: i32) -> Self {
+        match fd {
+            fd if fd >= 0 => PidfdRecord::Fd(unsafe { OwnedFd::from_raw_fd(fd) }),
+            FAN_NOPIDFD => PidfdRecord::Exited,
+            _ => PidfdRecord::Failed,
+        }
+    }
+
+    /// The pidfd, `None` for the sentinels.
+    #[inline]
+    pub fn fd(&self) -> Option<&OwnedFd> {
+        match self {
+            PidfdRecord::Fd(fd) => Some(fd),
+            _ => None,
+        }
+    }
+
+    /// Take ownership of the pidfd, `None` for the sentinels.
+    #[inline]
+    pub fn into_fd(self) -> Option<OwnedFd> {
+        match self {
+            PidfdRecord::Fd(fd) => Some(fd),
+            _ => None,
+        }
+    }
+}
+
+/// Iterator that decodes the information records that follow
+/// [`fanotify_event_metadata`] inside a single event.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::event::*;
+/// # use naughtyfy::flags::*;
+/// // DFID_NAME record: header, fsid, file_handle, name padded to 4 bytes
+/// let mut buf = vec![FAN_EVENT_INFO_TYPE_DFID_NAME, 0, 32, 0];
+/// buf.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]); // fsid
+/// buf.extend_from_slice(&8u32.to_ne_bytes()); // handle_bytes
+/// buf.extend_from_slice(&1i32.to_ne_bytes()); // handle_type
+/// buf.extend_from_slice(&[0xaa; 8]); // f_handle
+/// buf.extend_from_slice(b"foo\0");
+///
+/// // Safety: the buffer holds no pidfd record.
+/// let records: Vec<_> = unsafe { InfoIter::new(&buf) }.collect::<Result<_, _>>().unwrap();
+/// match &records[0] {
+///     InfoRecord::DfidName(fid) => {
+///         assert_eq!(fid.fsid.val, [1, 2]);
+///         assert_eq!(fid.handle.handle_type, 1);
+///         assert_eq!(fid.handle.bytes, vec![0xaa; 8]);
+///         assert_eq!(fid.name.as_deref(), Some(std::ffi::OsStr::new("foo")));
+///     }
+///     other => panic!("unexpected {other:?}"),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct InfoIter<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    /// Offset of `buf` inside the read buffer, used for error reporting.
+    base: usize,
+    /// Skip pidfd records instead of taking ownership of the fd,
+    /// set when someone else owns them (Eg: [`EventRef`]).
+    skip_pidfd: bool,
+}
+
+impl<'a> InfoIter<'a> {
+    /// Create a new iterator over the bytes between `metadata_len`
+    /// and `event_len` of an event.
+    ///
+    /// # Safety
+    /// Yielded [`InfoRecord::Pidfd`] records own the pidfd, `buf` must come
+    /// from a read(2) on a fanotify fd (or hold no pidfd record) and must
+    /// not be decoded twice.
+    pub unsafe fn new(buf: &'a [u8]) -> Self {
+        InfoIter {
+            buf,
+            offset: 0,
+            base: 0,
+            skip_pidfd: false,
+        }
+    }
+
+    /// Stop iterating and report a malformed record at current offset.
+    fn malformed(&mut self) -> FanotifyError {
+        let offset = self.base + self.offset;
+        self.offset = self.buf.len();
+        FanotifyError::Malformed(offset)
+    }
+
+    /// Decode fsid, handle and optionally the name of a FID type record.
+    fn fid(record: &[u8], with_name: bool) -> Option<FidRecord> {
+        if record.len() < FAN_INFO_FID_LEN + FILE_HANDLE_LEN {
+            return None;
+        }
+        let info =
+            unsafe { std::ptr::read_unaligned(record.as_ptr() as *const fanotify_event_info_fid) };
+        let handle = &record[FAN_INFO_FID_LEN..];
+        let header = unsafe { std::ptr::read_unaligned(handle.as_ptr() as *const file_handle) };
+        let handle_end = FILE_HANDLE_LEN.checked_add(header.handle_bytes as usize)?;
+        let bytes = handle.get(FILE_HANDLE_LEN..handle_end)?.to_vec();
+        let name = if with_name {
+            // Name is NUL terminated and the record is padded after it.
+            let rest = &handle[handle_end..];
+            let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+            Some(OsString::from_vec(rest[..len].to_vec()))
+        } else {
+            None
+        };
+        Some(FidRecord {
+            fsid: info.fsid,
+            handle: FileHandle {
+                handle_type: header.handle_type,
+                bytes,
+            },
+            name,
+        })
+    }
+
+    /// Decode a record whose header says it's `info_type`, pidfd
+    /// records take ownership of the fd, see [`InfoIter::new()`].
+    fn decode(info_type: u8, record: &[u8]) -> Option<InfoRecord> {
+        let record = match info_type {
+            FAN_EVENT_INFO_TYPE_FID => InfoRecord::Fid(Self::fid(record, false)?),
+            FAN_EVENT_INFO_TYPE_DFID => InfoRecord::Dfid(Self::fid(record, false)?),
+            FAN_EVENT_INFO_TYPE_DFID_NAME => InfoRecord::DfidName(Self::fid(record, true)?),
+            FAN_EVENT_INFO_TYPE_NEW_DFID_NAME => InfoRecord::NewDfidName(Self::fid(record, true)?),
+            FAN_EVENT_INFO_TYPE_OLD_DFID_NAME => InfoRecord::OldDfidName(Self::fid(record, true)?),
+            FAN_EVENT_INFO_TYPE_PIDFD => {
+                let info: fanotify_event_info_pidfd = read_record(record)?;
+                InfoRecord::Pidfd(unsafe { PidfdRecord::from_raw(info.pidfd) })
+            }
+            FAN_EVENT_INFO_TYPE_ERROR => {
+                let info: fanotify_event_info_error = read_record(record)?;
+                InfoRecord::Error {
+                    error: info.error,
+                    error_count: info.error_count,
+                }
+            }
+            FAN_EVENT_INFO_TYPE_RANGE => {
+                let info: fanotify_event_info_range = read_record(record)?;
+                InfoRecord::Range {
+                    offset: info.offset,
+                    count: info.count,
+                }
+            }
+            FAN_EVENT_INFO_TYPE_MNT => {
+                let info: fanotify_event_info_mnt = read_record(record)?;
+                InfoRecord::Mnt {
+                    mnt_id: info.mnt_id,
+                }
+            }
+            _ => InfoRecord::Unknown {
+                info_type,
+                data: record[FAN_INFO_HEADER_LEN..].to_vec(),
+            },
+        };
+        Some(record)
+    }
+}
+
+/// Copy a fixed size record out of the buffer if it's long enough.
+fn read_record<T>(record: &[u8]) -> Option<T> {
+    if record.len() < mem::size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { std::ptr::read_unaligned(record.as_ptr() as *const T) })
+}
+
+impl Iterator for InfoIter<'_> {
+    type Item = Result<InfoRecord, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.buf[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let Some(header) = read_record::<fanotify_event_info_header>(rest) else {
+            return Some(Err(self.malformed()));
+        };
+        let len = header.len as usize;
+        if len < FAN_INFO_HEADER_LEN || len > rest.len() {
+            return Some(Err(self.malformed()));
+        }
+        if self.skip_pidfd && header.info_type == FAN_EVENT_INFO_TYPE_PIDFD {
+            self.offset += len;
+            return self.next();
+        }
+        match Self::decode(header.info_type, &rest[..len]) {
+            Some(record) => {
+                self.offset += len;
+                Some(Ok(record))
+            }
+            None => Some(Err(self.malformed())),
+        }
+    }
+}
+
+/// Walks the headers of a read buffer by `event_len` without taking
+/// ownership of anything, shared by [`EventIter`] and [`Events`].
+#[derive(Debug)]
+struct RecordIter<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+/// A validated record, its header and the information records bytes
+/// along with their offset in the buffer.
+struct Record<'a> {
+    metadata: fanotify_event_metadata,
+    info: &'a [u8],
+    info_offset: usize,
+}
+
+impl<'a> RecordIter<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        RecordIter { buf, offset: 0 }
+    }
+
+    /// Stop iterating and report a malformed record at current offset.
+    fn malformed(&mut self) -> FanotifyError {
+        let offset = self.offset;
+        self.offset = self.buf.len();
+        FanotifyError::Malformed(offset)
+    }
+}
+
+impl<'a> Iterator for RecordIter<'a> {
+    type Item = Result<Record<'a>, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.buf;
+        let rest = &buf[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        if rest.len() < FAN_EVENT_METADATA_LEN {
+            return Some(Err(self.malformed()));
+        }
+
+        // Buffer is a plain byte buffer, so the header may be unaligned.
+        let metadata =
+            unsafe { std::ptr::read_unaligned(rest.as_ptr() as *const fanotify_event_metadata) };
+        if metadata.vers as u32 != FANOTIFY_METADATA_VERSION {
+            // Don't take ownership of an fd we can't trust.
+            self.offset = buf.len();
+            return Some(Err(FanotifyError::Version(metadata.vers)));
+        }
+
+        let event_len = metadata.event_len as usize;
+        let metadata_len = metadata.metadata_len as usize;
+        if metadata_len < FAN_EVENT_METADATA_LEN
+            || event_len < metadata_len
+            || event_len > rest.len()
+        {
+            return Some(Err(self.malformed()));
+        }
+
+        let info_offset = self.offset + metadata_len;
+        self.offset += event_len;
+        Some(Ok(Record {
+            metadata,
+            info: &rest[metadata_len..event_len],
+            info_offset,
+        }))
+    }
+}
+
+/// Wrap the event fd of `metadata`, anything negative is [`FAN_NOFD`].
+fn owned_fd(metadata: &fanotify_event_metadata) -> Option<OwnedFd> {
+    match metadata.fd {
+        fd if fd >= 0 => Some(unsafe { OwnedFd::from_raw_fd(fd) }),
+        _ => None,
+    }
+}
+
+/// Iterator that walks a buffer filled by [`read()`] record by record
+/// using `event_len`.
+///
+/// Yields an error and stops if a record carries a metadata version other
+/// than [`FANOTIFY_METADATA_VERSION`] or its lengths don't fit in the
+/// buffer. Records already yielded stay valid.
+///
+/// After an information record fails to decode, the fds (event fds and
+/// pidfds) of the following records are closed. Records following a broken
+/// metadata header can't be located, their fds are leaked.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::event::*;
+/// # use naughtyfy::flags::*;
+/// // Empty buffer, no events
+/// let mut iter = unsafe { EventIter::new(&[]) };
+/// assert!(iter.next().is_none());
+///
+/// // Too short to hold even a header
+/// let mut iter = unsafe { EventIter::new(&[0u8; 4]) };
+/// assert!(iter.next().unwrap().is_err());
+/// assert!(iter.next().is_none());
+///
+/// // Records of different lengths, `event_len` leads to the next one
+/// fn record(mask: u64, info: &[u8]) -> Vec<u8> {
+///     let mut buf = Vec::new();
+///     buf.extend_from_slice(&(24 + info.len() as u32).to_ne_bytes()); // event_len
+///     buf.extend_from_slice(&[FANOTIFY_METADATA_VERSION as u8, 0]); // vers, reserved
+///     buf.extend_from_slice(&24u16.to_ne_bytes()); // metadata_len
+///     buf.extend_from_slice(&mask.to_ne_bytes());
+///     buf.extend_from_slice(&FAN_NOFD.to_ne_bytes());
+///     buf.extend_from_slice(&42i32.to_ne_bytes()); // pid
+///     buf.extend_from_slice(info);
+///     buf
+/// }
+/// // DFID_NAME record: header, fsid, file_handle, name padded to 4 bytes
+/// let mut dfid_name = vec![FAN_EVENT_INFO_TYPE_DFID_NAME, 0, 32, 0];
+/// dfid_name.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]); // fsid
+/// dfid_name.extend_from_slice(&8u32.to_ne_bytes()); // handle_bytes
+/// dfid_name.extend_from_slice(&1i32.to_ne_bytes()); // handle_type
+/// dfid_name.extend_from_slice(&[0xaa; 8]); // f_handle
+/// dfid_name.extend_from_slice(b"foo\0");
+/// let mut buf = record(FAN_CREATE, &dfid_name);
+/// buf.extend(record(FAN_Q_OVERFLOW, &[]));
+///
+/// // Safety: the records carry no fd.
+/// let events: Vec<_> = unsafe { EventIter::new(&buf) }.collect::<Result<_, _>>().unwrap();
+/// assert_eq!(events.len(), 2);
+/// assert_eq!(events[0].mask, FAN_CREATE);
+/// match &events[0].info[..] {
+///     [InfoRecord::DfidName(fid)] => {
+///         assert_eq!(fid.name.as_deref(), Some(std::ffi::OsStr::new("foo")))
+///     }
+///     other => panic!("unexpected {other:?}"),
+/// }
+/// // Info records end at `event_len`, the next header isn't one of them.
+/// assert_eq!(events[1].mask, FAN_Q_OVERFLOW);
+/// assert!(events[1].info.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct EventIter<'a> {
+    records: RecordIter<'a>,
+}
+
+impl<'a> EventIter<'a> {
+    /// Create a new iterator over the bytes returned by a read(2) on a
+    /// fanotify file descriptor.
+    ///
+    /// # Safety
+    /// Yielded events take ownership of the fds in the records, `buf` must
+    /// come from a read(2) on a fanotify fd (or hold no fds, Eg:
+    /// [`FAN_NOFD`]) and must not be iterated twice.
+    pub unsafe fn new(buf: &'a [u8]) -> Self {
+        EventIter {
+            records: RecordIter::new(buf),
+        }
+    }
+}
+
+impl Iterator for EventIter<'_> {
+    type Item = Result<Event, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e)),
+        };
+        let fd = owned_fd(&record.metadata);
+        // Safety: the records are decoded once, as the events are yielded.
+        let mut info = unsafe { InfoIter::new(record.info) };
+        info.base = record.info_offset;
+        match info.collect() {
+            Ok(info) => Some(Ok(Event {
+                mask: record.metadata.mask,
+                pid: record.metadata.pid,
+                fd,
+                info,
+            })),
+            Err(e) => {
+                // Later records are still well formed, close their fds.
+                self.records
+                    .by_ref()
+                    .map_while(Result::ok)
+                    .for_each(|record| {
+                        EventRef::new(record);
+                    });
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An event borrowed from the read buffer of a [`Fanotify`] group,
+/// returned by [`Fanotify::events()`].
+///
+/// Like [`Event`] it owns the event fd, but information records are
+/// decoded lazily from the buffer with [`EventRef::info()`], so handling
+/// an event allocates nothing unless its records are decoded.
+#[derive(Debug)]
+pub struct EventRef<'a> {
+    /// Bit mask describing the event, see [`fanotify_event_metadata::mask`].
+    pub mask: u64,
+    /// PID (or TID with [`FAN_REPORT_TID`]) of the process that caused the event.
+    pub pid: i32,
+    fd: Option<OwnedFd>,
+    pidfd: Option<PidfdRecord>,
+    info: &'a [u8],
+    info_offset: usize,
+}
+
+impl<'a> EventRef<'a> {
+    /// Take ownership of the fds in `record`.
+    fn new(record: Record<'a>) -> Self {
+        // Pidfds must be closed even if records are never decoded,
+        // only headers are walked to find one.
+        let mut pidfd = None;
+        let mut rest = record.info;
+        while let Some(header) = read_record::<fanotify_event_info_header>(rest) {
+            let len = (header.len as usize).min(rest.len());
+            if len < FAN_INFO_HEADER_LEN {
+                break;
+            }
+            if header.info_type == FAN_EVENT_INFO_TYPE_PIDFD {
+                if let Some(InfoRecord::Pidfd(record)) = InfoIter::decode(header.info_type, rest) {
+                    pidfd = Some(record);
+                }
+            }
+            rest = &rest[len..];
+        }
+        EventRef {
+            mask: record.metadata.mask,
+            pid: record.metadata.pid,
+            fd: owned_fd(&record.metadata),
+            pidfd,
+            info: record.info,
+            info_offset: record.info_offset,
+        }
+    }
+
+    /// Fd of the object being accessed, see [`Event::fd()`].
+    #[inline]
+    pub fn fd(&self) -> Option<&OwnedFd> {
+        self.fd.as_ref()
+    }
+
+    /// Take ownership of the event fd, see [`Event::take_fd()`].
+    #[inline]
+    pub fn take_fd(&mut self) -> Option<OwnedFd> {
+        self.fd.take()
+    }
+
+    /// Typed [`mask`](Self::mask).
+    #[inline]
+    pub fn event_mask(&self) -> EventMask {
+        EventMask::from(self.mask)
+    }
+
+    /// What happened, see [`Event::kinds()`].
+    #[inline]
+    pub fn kinds(&self) -> EventKinds {
+        EventKinds::new(self.mask)
+    }
+
+    /// `true` if the object is a directory ([`FAN_ONDIR`]).
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.mask & FAN_ONDIR != 0
+    }
+
+    /// PID (or TID with [`FAN_REPORT_TID`]), see [`Event::pid()`].
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Pidfd of the process that caused the event if group was
+    /// initialised with [`FAN_REPORT_PIDFD`].
+    #[inline]
+    pub fn pidfd(&self) -> Option<&OwnedFd> {
+        self.pidfd.as_ref()?.fd()
+    }
+
+    /// Pidfd record, see [`Event::pidfd_record()`].
+    #[inline]
+    pub fn pidfd_record(&self) -> Option<&PidfdRecord> {
+        self.pidfd.as_ref()
+    }
+
+    /// Decode the information records of the event.
+    ///
+    /// Pidfd records are not yielded, they are available
+    /// through [`EventRef::pidfd_record()`].
+    pub fn info(&self) -> InfoIter<'a> {
+        InfoIter {
+            buf: self.info,
+            offset: 0,
+            base: self.info_offset,
+            skip_pidfd: true,
+        }
+    }
+
+    /// Decode all information records and detach the event from the buffer.
+    pub fn into_owned(mut self) -> Result<Event, FanotifyError> {
+        let mut info = self.info().collect::<Result<Vec<_>, _>>()?;
+        if let Some(pidfd) = self.pidfd.take() {
+            info.push(InfoRecord::Pidfd(pidfd));
+        }
+        Ok(Event {
+            mask: self.mask,
+            pid: self.pid,
+            fd: self.fd.take(),
+            info,
+        })
+    }
+}
+
+/// Iterator over the events of a single read into the buffer of a
+/// [`Fanotify`] group, returned by [`Fanotify::events()`].
+///
+/// The buffer is validated before iteration starts, so every record is
+/// yielded. Fds of events that are not iterated are closed on drop.
+#[derive(Debug)]
+pub struct Events<'a> {
+    records: RecordIter<'a>,
+}
+
+impl<'a> Events<'a> {
+    /// Validate all records in `buf`, closing their fds if any is invalid.
+    pub(crate) fn new(buf: &'a [u8]) -> Result<Self, FanotifyError> {
+        if let Some(Err(e)) = RecordIter::new(buf).find(Result::is_err) {
+            // Close what was valid before the broken record.
+            RecordIter::new(buf)
+                .map_while(Result::ok)
+                .for_each(|record| {
+                    EventRef::new(record);
+                });
+            return Err(e);
+        }
+        Ok(Events {
+            records: RecordIter::new(buf),
+        })
+    }
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = EventRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Validated in `Events::new()`, can't fail.
+        self.records.next()?.ok().map(EventRef::new)
+    }
+}
+
+impl Drop for Events<'_> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
@@ -0,0 +1,289 @@
+//! Resolution of the file handles carried by [`FidRecord`]s.
+//!
+//! A group initialised with [`FAN_REPORT_FID`] or [`FAN_REPORT_DFID_NAME`]
+//! gets no event fd, the object is identified by an fsid and a file handle
+//! instead. To turn that into an open file the handle has to be passed to
+//! open_by_handle_at(2) together with an fd of the filesystem it belongs to.
+//! [`FidResolver`] finds that filesystem by comparing the fsid with
+//! statfs(2) of every entry of `/proc/self/mountinfo`.
+//!
+//! Opening handles requires the `CAP_DAC_READ_SEARCH` capability.
+
+use crate::{errors::*, event::*, types::*};
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsString},
+    mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    path::PathBuf,
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::flags::*;
+
+/// Mount fd and mount point of a filesystem found in `/proc/self/mountinfo`.
+#[derive(Debug)]
+struct Mount {
+    fd: OwnedFd,
+    path: PathBuf,
+}
+
+/// Maps the fsid of [`FidRecord`]s to a mount of that filesystem
+/// and opens the handle with open_by_handle_at(2).
+///
+/// Mount fds are opened once per fsid and cached for the lifetime of
+/// the resolver. If a filesystem is mounted after it was looked up
+/// unsuccessfully, the next lookup scans mountinfo again.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::event::*;
+/// # use naughtyfy::fid::*;
+/// # use naughtyfy::flags::*;
+/// let dir = std::env::temp_dir().join("naughtyfy-fid-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let fd = &init(FAN_CLASS_NOTIF | FAN_NONBLOCK | FAN_REPORT_DFID_NAME, 0);
+/// match fd {
+///     Ok(fd) => {
+///         mark(fd, FAN_MARK_ADD, FAN_CREATE, AT_FDCWD, dir.to_str().unwrap()).unwrap();
+///         std::fs::write(dir.join("created"), "").unwrap();
+///         let mut resolver = FidResolver::new();
+///         for event in read_with_fid(fd).unwrap() {
+///             for info in &event.info {
+///                 if let InfoRecord::DfidName(fid) = info {
+///                     let (_dir_fd, path) = resolver.resolve(fid).unwrap();
+///                     assert_eq!(path, dir.join("created"));
+///                 }
+///             }
+///         }
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct FidResolver {
+    mounts: HashMap<__kernel_fsid_t, Mount>,
+}
+
+impl FidResolver {
+    /// Create a resolver with empty mount cache.
+    pub fn new() -> Self {
+        FidResolver::default()
+    }
+
+    /// Open the object identified by `fid` with open_by_handle_at(2)
+    /// using `flags` (Eg: `libc::O_RDONLY`).
+    ///
+    /// For the DFID variants of [`InfoRecord`] the directory is opened,
+    /// the entry name is not looked up.
+    ///
+    /// Fails with [`FanotifyError::Resolve`] carrying `ESTALE` if the object
+    /// no longer exists and `ENODEV` if no mount of the filesystem can be opened.
+    pub fn open(&mut self, fid: &FidRecord, flags: i32) -> Result<OwnedFd, FanotifyError> {
+        let mount = self.mount(&fid.fsid)?;
+        let mut handle = Vec::with_capacity(mem::size_of::<file_handle>() + fid.handle.bytes.len());
+        handle.extend_from_slice(&(fid.handle.bytes.len() as u32).to_ne_bytes());
+        handle.extend_from_slice(&fid.handle.handle_type.to_ne_bytes());
+        handle.extend_from_slice(&fid.handle.bytes);
+        unsafe {
+            match libc::syscall(
+                libc::SYS_open_by_handle_at,
+                mount.fd.as_raw_fd(),
+                handle.as_mut_ptr(),
+                flags,
+            ) {
+                -1 => Err(FanotifyError::Resolve(Errno::last())),
+                fd => Ok(OwnedFd::from_raw_fd(fd as i32)),
+            }
+        }
+    }
+
+    /// Open the object identified by `fid` with `O_PATH` and
+    /// return it with its current path.
+    ///
+    /// For records that carry an entry name other than `"."` the returned
+    /// fd refers to the directory while the path has the name appended.
+    pub fn resolve(&mut self, fid: &FidRecord) -> Result<(OwnedFd, PathBuf), FanotifyError> {
+        let fd = self.open(fid, libc::O_PATH | libc::O_CLOEXEC)?;
+        let mut path = FdToPath::path(&fd).map_err(|e| FanotifyError::Resolve(Errno::from(&e)))?;
+        match &fid.name {
+            Some(name) if name != "." => path.push(name),
+            _ => {}
+        }
+        Ok((fd, path))
+    }
+
+    /// Fill the paths of both locations of `rename`, a location whose
+    /// directory can't be opened (Eg: it was deleted since) is left `None`.
+    pub fn resolve_rename(&mut self, rename: &mut Rename) {
+        for entry in [&mut rename.from, &mut rename.to].into_iter().flatten() {
+            let fid = FidRecord {
+                name: Some(entry.name.clone()),
+                ..entry.dir.clone()
+            };
+            entry.path = self.resolve(&fid).ok().map(|(_, path)| path);
+        }
+    }
+
+    /// Mount point of the filesystem with `fsid`, if it can be found.
+    pub fn mount_point(&mut self, fsid: &__kernel_fsid_t) -> Result<PathBuf, FanotifyError> {
+        Ok(self.mount(fsid)?.path.clone())
+    }
+
+    /// Cached mount for `fsid`, scanning mountinfo on a miss.
+    fn mount(&mut self, fsid: &__kernel_fsid_t) -> Result<&Mount, FanotifyError> {
+        if !self.mounts.contains_key(fsid) {
+            let mount = mount_points()?
+                .into_iter()
+                .filter(|path| statfs_fsid(path).as_ref() == Some(fsid))
+                // File bind mounts can't be opened as directory, try the next one.
+                .find_map(|path| {
+                    Some(Mount {
+                        fd: open_dir(&path)?,
+                        path,
+                    })
+                })
+                .ok_or(FanotifyError::Resolve(Errno::ENODEV))?;
+            self.mounts.insert(*fsid, mount);
+        }
+        Ok(&self.mounts[fsid])
+    }
+}
+
+/// `AT_HANDLE_FID` of name_to_handle_at(2), encode the handle the way
+/// fanotify does even if the filesystem can't open it (since 6.5).
+const AT_HANDLE_FID: i32 = 0x200;
+
+/// Largest file handle the kernel hands out.
+const MAX_HANDLE_SZ: usize = 128;
+
+/// Identify `path` the way a [`FAN_REPORT_FID`] group does, without name.
+///
+/// Comparing the result with the [`FidRecord`]s of events tells which
+/// object they are about without opening handles, which needs no capability.
+/// The final component of `path` isn't followed if it's a symlink.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::fid::*;
+/// let dir = std::env::temp_dir();
+/// let fid = path_to_fid(&dir).unwrap();
+/// assert_eq!(fid, path_to_fid(&dir).unwrap());
+/// assert!(fid.name.is_none());
+/// ```
+pub fn path_to_fid(path: &std::path::Path) -> Result<FidRecord, FanotifyError> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| FanotifyError::InvalidPath(path.as_os_str().to_os_string()))?;
+    let fsid = statfs_fsid(path).ok_or_else(|| FanotifyError::Resolve(Errno::last()))?;
+    let mut handle = vec![0u8; mem::size_of::<file_handle>() + MAX_HANDLE_SZ];
+    let mut mount_id: libc::c_int = 0;
+    // Older kernels reject AT_HANDLE_FID, the plain handle is the same
+    // for filesystems that can be exported.
+    for flags in [AT_HANDLE_FID, 0] {
+        handle[..4].copy_from_slice(&(MAX_HANDLE_SZ as u32).to_ne_bytes());
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_name_to_handle_at,
+                libc::AT_FDCWD,
+                c_path.as_ptr(),
+                handle.as_mut_ptr(),
+                &mut mount_id,
+                flags,
+            )
+        };
+        if ret == 0 {
+            let header = unsafe { std::ptr::read_unaligned(handle.as_ptr() as *const file_handle) };
+            let start = mem::size_of::<file_handle>();
+            return Ok(FidRecord {
+                fsid,
+                handle: FileHandle {
+                    handle_type: header.handle_type,
+                    bytes: handle[start..start + header.handle_bytes as usize].to_vec(),
+                },
+                name: None,
+            });
+        }
+        if Errno::last() != Errno::EINVAL {
+            break;
+        }
+    }
+    Err(FanotifyError::Resolve(Errno::last()))
+}
+
+/// All mount points listed in `/proc/self/mountinfo`.
+fn mount_points() -> Result<Vec<PathBuf>, FanotifyError> {
+    let mountinfo = std::fs::read("/proc/self/mountinfo")
+        .map_err(|e| FanotifyError::Resolve(Errno::from(&e)))?;
+    Ok(mountinfo
+        .split(|b| *b == b'\n')
+        // Fifth field is the mount point relative to our root.
+        .filter_map(|line| line.split(|b| *b == b' ').nth(4))
+        .map(|field| PathBuf::from(unescape(field)))
+        .collect())
+}
+
+/// Undo the octal escaping (`\040` for space etc.) mountinfo applies.
+fn unescape(field: &[u8]) -> OsString {
+    let mut out = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (field[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    OsString::from_vec(out)
+}
+
+/// fsid of the filesystem `path` lives on.
+pub(crate) fn statfs_fsid(path: &std::path::Path) -> Option<__kernel_fsid_t> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut buf = mem::MaybeUninit::<libc::statfs>::uninit();
+    unsafe {
+        match libc::statfs(path.as_ptr(), buf.as_mut_ptr()) {
+            0 => {
+                // `fsid_t` keeps its fields private, layout is the same.
+                Some(mem::transmute::<libc::fsid_t, __kernel_fsid_t>(
+                    buf.assume_init().f_fsid,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Open directory `path` to be used as mount fd, open_by_handle_at(2)
+/// rejects `O_PATH` descriptors.
+fn open_dir(path: &std::path::Path) -> Option<OwnedFd> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        match libc::open(
+            path.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        ) {
+            -1 => None,
+            fd => Some(OwnedFd::from_raw_fd(fd)),
+        }
+    }
+}
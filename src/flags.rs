@@ -6,6 +6,8 @@ extern crate libc;
 #[allow(unused_imports)]
 use crate::api::read_with_fid;
 #[allow(unused_imports)]
+use crate::types::fanotify_response_info_audit_rule;
+#[allow(unused_imports)]
 use libc::{EAGAIN, EEXIST, EINVAL, ENOTDIR};
 /* the following events that user-space can register for */
 
@@ -62,7 +64,7 @@ pub const FAN_OPEN_EXEC: u64 = 0x00001000; /* File was opened for exec */
 pub const FAN_Q_OVERFLOW: u64 = 0x00004000; /* Event queued overflowed */
 
 /// Represents filesystem error
-pub const FAN_FS_ERROR: u32 = 0x00008000; /* Filesystem error */
+pub const FAN_FS_ERROR: u64 = 0x00008000; /* Filesystem error */
 
 /// Create an event when a permission to open a file or
 /// directory is requested.  An fanotify file descriptor
@@ -190,41 +192,71 @@ pub const FAN_DENY: u32 = 0x02;
 /// Bit mask to create audit record for result
 pub const FAN_AUDIT: u32 = 0x10;
 
+/// Bit mask to indicate that information records follow the response.
+pub const FAN_INFO: u32 = 0x20;
+
+/* errno other than EPERM can specified in upper byte of deny response */
+/// Number of bits of the errno in a deny response.
+pub const FAN_ERRNO_BITS: u32 = 8;
+
+/// Offset of the errno in a deny response.
+pub const FAN_ERRNO_SHIFT: u32 = 32 - FAN_ERRNO_BITS;
+
+/// Mask of the errno in a deny response.
+pub const FAN_ERRNO_MASK: u32 = (1 << FAN_ERRNO_BITS) - 1;
+
+/// Convenience macro - Deny the file operation with `err` instead of `EPERM`.
+pub const fn fan_deny_errno(err: u32) -> u32 {
+    FAN_DENY | ((err & FAN_ERRNO_MASK) << FAN_ERRNO_SHIFT)
+}
+
+/// No information record follows the response.
+pub const FAN_RESPONSE_INFO_NONE: u8 = 0;
+
+/// [`fanotify_response_info_audit_rule`] follows the response.
+pub const FAN_RESPONSE_INFO_AUDIT_RULE: u8 = 1;
+
 /// Indicates a queue overflow.
 pub const FAN_NOFD: i32 = -1;
 
+/// Pidfd of a process that was gone before the event was read.
+pub const FAN_NOPIDFD: i32 = FAN_NOFD;
+
+/// Pidfd that kernel failed to create.
+pub const FAN_EPIDFD: i32 = -2;
+
 /// This value allows only read access.
-pub const O_RDONLY: u32 = 00000000;
+pub const O_RDONLY: u32 = libc::O_RDONLY as u32;
 
 /// This value allows only write access.
-pub const O_WRONLY: u32 = 1;
+pub const O_WRONLY: u32 = libc::O_WRONLY as u32;
 
 /// This value allows read and write access.
-pub const O_RDWR: u32 = 2;
+pub const O_RDWR: u32 = libc::O_RDWR as u32;
 
 /// The file is opened in append mode.
-pub const O_APPEND: u32 = 2000;
+pub const O_APPEND: u32 = libc::O_APPEND as u32;
 
 /// When possible, the file is opened in nonblocking mode.
-pub const O_NONBLOCK: u32 = 4000;
+pub const O_NONBLOCK: u32 = libc::O_NONBLOCK as u32;
 
 /// Write operations on the file will complete according to
 /// the requirements of synchronized I/O data integrity
 /// completion.
-pub const O_DSYNC: u32 = 10000; /* direct disk access hint */
+pub const O_DSYNC: u32 = libc::O_DSYNC as u32; /* direct disk access hint */
 
 /// Enable support for files exceeding 2 GB.  Failing to set
 /// this flag will result in an EOVERFLOW error when trying to
 /// open a large file which is monitored by an fanotify group
 /// on a 32-bit system.
-pub const O_LARGEFILE: u32 = 0x40000;
+pub const O_LARGEFILE: u32 = libc::O_LARGEFILE as u32;
 
 /// Do not update the file last access time (st_atime in the
 /// inode) when the file is [read(2)](https://man7.org/linux/man-pages/man2/read.2.html).
-pub const O_NOATIME: u32 = 1000000;
+pub const O_NOATIME: u32 = libc::O_NOATIME as u32;
 
 /// Enable the close-on-exec flag for the new file descriptor.
-pub const O_CLOEXEC: u32 = 2000000; /* set close_on_exec */
+pub const O_CLOEXEC: u32 = libc::O_CLOEXEC as u32; /* set close_on_exec */
 
 /// Special value used to indicate openat should use the current working directory
 pub const AT_FDCWD: i32 = -100;
@@ -360,6 +392,10 @@ pub const FAN_MARK_MOUNT: u32 = 0x00000010;
 /// point will be monitored.
 pub const FAN_MARK_FILESYSTEM: u32 = 0x00000100;
 
+/// Mark the mount namespace of the nsfs file specified by pathname
+/// (Eg: `/proc/self/ns/mnt`) for mount events (since Linux 6.14).
+pub const FAN_MARK_MNTNS: u32 = FAN_MARK_MOUNT | FAN_MARK_FILESYSTEM;
+
 /// Convenience macro - [`FAN_MARK_IGNORE`] requires [`FAN_MARK_IGNORED_SURV_MODIFY`]
 /// for non-inode mark types.
 pub const FAN_MARK_IGNORE_SURV: u32 = FAN_MARK_IGNORE | FAN_MARK_IGNORED_SURV_MODIFY;
@@ -395,6 +431,35 @@ pub const FAN_ALL_PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM;
 #[allow(deprecated)] // only allowing it because of 1-1 mapping
 pub const FAN_ALL_OUTGOING_EVENTS: u64 = FAN_ALL_EVENTS | FAN_ALL_PERM_EVENTS | FAN_Q_OVERFLOW;
 
+/* Info types for information records following fanotify_event_metadata */
+/// Record identifies the object of the event by file handle.
+pub const FAN_EVENT_INFO_TYPE_FID: u8 = 1;
+
+/// Record identifies the parent directory by file handle and
+/// holds the name of the entry.
+pub const FAN_EVENT_INFO_TYPE_DFID_NAME: u8 = 2;
+
+/// Record identifies the parent directory by file handle.
+pub const FAN_EVENT_INFO_TYPE_DFID: u8 = 3;
+
+/// Record holds a pidfd for the process that generated the event.
+pub const FAN_EVENT_INFO_TYPE_PIDFD: u8 = 4;
+
+/// Record holds error code and count of a [`FAN_FS_ERROR`] event.
+pub const FAN_EVENT_INFO_TYPE_ERROR: u8 = 5;
+
+/// Record holds the file range of the access.
+pub const FAN_EVENT_INFO_TYPE_RANGE: u8 = 6;
+
+/// Record holds the mount id of a mount event.
+pub const FAN_EVENT_INFO_TYPE_MNT: u8 = 7;
+
+/// Same as [`FAN_EVENT_INFO_TYPE_DFID_NAME`] for the old location of a [`FAN_RENAME`].
+pub const FAN_EVENT_INFO_TYPE_OLD_DFID_NAME: u8 = 10;
+
+/// Same as [`FAN_EVENT_INFO_TYPE_DFID_NAME`] for the new location of a [`FAN_RENAME`].
+pub const FAN_EVENT_INFO_TYPE_NEW_DFID_NAME: u8 = 12;
+
 /// Compare [`fanotify_event_metadata.vers`] to verify
 /// that the structures returned at run time match the
 /// structures defined at compile time.  In case of a
@@ -0,0 +1,578 @@
+//! Owning wrapper around a fanotify group.
+//!
+//! [`Fanotify`] keeps the fd returned by [`init()`] together with the flags
+//! it was created with, so calls that depend on the group configuration
+//! (Eg: decoding of events from a [`FAN_REPORT_FID`] group) don't need
+//! the caller to remember them.
+
+use crate::{
+    api::{self, DEFAULT_EVENT_BUFFER_LEN},
+    errors::*,
+    event::*,
+    mark::{c_path, MarkTarget},
+    mask::*,
+    permission::{PermissionRequest, Response},
+    registry::{MarkKind, MarkObject, MarkTable, Registry},
+    shutdown::{self, ShutdownHandle},
+    types::*,
+    validate::*,
+};
+use std::{
+    mem,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    time::Duration,
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{api::*, flags::*};
+
+/// Init flags after which kernel attaches information records to events.
+const FAN_REPORT_INFO_FLAGS: u32 = FAN_REPORT_FID
+    | FAN_REPORT_DIR_FID
+    | FAN_REPORT_NAME
+    | FAN_REPORT_TARGET_FID
+    | FAN_REPORT_PIDFD;
+
+/// A fanotify group, owns the fd of the event queue and
+/// remembers the flags passed to [`init()`].
+///
+/// The fd is closed when the group is dropped.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::group::*;
+/// let dir = std::env::temp_dir().join("naughtyfy-group-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// match Fanotify::new(FAN_CLASS_NOTIF | FAN_NONBLOCK | FAN_REPORT_DFID_NAME, 0) {
+///     Ok(mut group) => {
+///         assert!(group.reports_info());
+///         group.mark(0, FAN_CREATE, AT_FDCWD, dir.to_str().unwrap()).unwrap();
+///         std::fs::write(dir.join("created"), "").unwrap();
+///         let events = group.read_events().unwrap();
+///         assert_eq!(events.len(), 1);
+///         assert_eq!(events[0].info.len(), 1);
+///         group.unmark(0, FAN_CREATE, AT_FDCWD, dir.to_str().unwrap()).unwrap();
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Fanotify {
+    fd: OwnedFd,
+    flags: u32,
+    event_f_flags: u32,
+    /// Reused by every read, only its capacity matters between reads.
+    buf: Vec<u8>,
+    /// Written for permission requests dropped without an answer.
+    default_response: u32,
+    /// Polled along with the group by interruptible reads.
+    shutdown: Option<ShutdownHandle>,
+    /// Marks placed through this group.
+    marks: MarkTable,
+    /// Events read by a [`drain()`](Self::drain) that failed, returned by
+    /// the next one.
+    drained: Vec<Event>,
+}
+
+impl Fanotify {
+    /// Initializes a new fanotify group, see [`init()`] for the
+    /// meaning of `flags` and `event_f_flags`.
+    ///
+    /// Events are read into a buffer of [`DEFAULT_EVENT_BUFFER_LEN`] bytes.
+    /// Invalid flag combinations fail with [`FanotifyError::Invalid`],
+    /// see [`check_init()`].
+    pub fn new(flags: u32, event_f_flags: u32) -> Result<Self, FanotifyError> {
+        Self::with_buffer_len(flags, event_f_flags, DEFAULT_EVENT_BUFFER_LEN)
+    }
+
+    /// Same as [`Fanotify::new()`] with a read buffer of `len` bytes.
+    ///
+    /// A read returns as many whole events as fit in the buffer, `len`
+    /// should be at least the size of the largest event the group can
+    /// receive or [`read()`] fails with `EINVAL`.
+    pub fn with_buffer_len(
+        flags: u32,
+        event_f_flags: u32,
+        len: usize,
+    ) -> Result<Self, FanotifyError> {
+        check_init(InitFlags::from(flags))?;
+        Ok(Fanotify {
+            fd: api::init(flags, event_f_flags)?,
+            flags,
+            event_f_flags,
+            buf: Vec::with_capacity(len),
+            default_response: FAN_ALLOW,
+            shutdown: None,
+            marks: MarkTable::default(),
+            drained: Vec::new(),
+        })
+    }
+
+    /// Same as [`Fanotify::new()`] with typed flags.
+    pub fn with_flags(flags: InitFlags, event_f_flags: EventFFlags) -> Result<Self, FanotifyError> {
+        Self::new(flags.bits(), event_f_flags.bits())
+    }
+
+    /// Size in bytes of the read buffer.
+    #[inline]
+    pub fn buffer_len(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Resize the read buffer to `len` bytes.
+    pub fn set_buffer_len(&mut self, len: usize) {
+        self.buf = Vec::with_capacity(len);
+    }
+
+    /// Flags the group was initialised with.
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Typed [`flags()`](Self::flags).
+    #[inline]
+    pub fn init_flags(&self) -> InitFlags {
+        InitFlags::from(self.flags)
+    }
+
+    /// File status flags set on event fds.
+    #[inline]
+    pub fn event_f_flags(&self) -> u32 {
+        self.event_f_flags
+    }
+
+    /// `true` if the group identifies filesystem objects by file handles
+    /// ([`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`]), events of such group
+    /// carry no fd.
+    #[inline]
+    pub fn reports_fid(&self) -> bool {
+        self.flags & (FAN_REPORT_FID | FAN_REPORT_DIR_FID) != 0
+    }
+
+    /// `true` if kernel attaches information records to the events of this group.
+    #[inline]
+    pub fn reports_info(&self) -> bool {
+        self.flags & FAN_REPORT_INFO_FLAGS != 0
+    }
+
+    /// Add the events in `mask` to the mark of the object defined by
+    /// `dirfd` and `path`. [`FAN_MARK_ADD`] is implied, `flags` may hold the
+    /// additional flags accepted by [`mark()`].
+    ///
+    /// Flags and mask are checked against the init flags of the group first,
+    /// invalid combinations fail with [`FanotifyError::Invalid`], see [`check_mark()`].
+    pub fn mark<P: ?Sized + Path>(
+        &self,
+        flags: u32,
+        mask: u64,
+        dirfd: i32,
+        path: &P,
+    ) -> Result<(), FanotifyError> {
+        self.mark_with(MarkFlags::from(flags), EventMask::from(mask), dirfd, path)
+    }
+
+    /// Remove the events in `mask` from the mark of the object defined by
+    /// `dirfd` and `path`. [`FAN_MARK_REMOVE`] is implied.
+    pub fn unmark<P: ?Sized + Path>(
+        &self,
+        flags: u32,
+        mask: u64,
+        dirfd: i32,
+        path: &P,
+    ) -> Result<(), FanotifyError> {
+        self.unmark_with(MarkFlags::from(flags), EventMask::from(mask), dirfd, path)
+    }
+
+    /// Same as [`Fanotify::mark()`] with typed flags and mask.
+    pub fn mark_with<P: ?Sized + Path>(
+        &self,
+        flags: MarkFlags,
+        mask: EventMask,
+        dirfd: i32,
+        path: &P,
+    ) -> Result<(), FanotifyError> {
+        let flags = MarkFlags::ADD | flags;
+        check_mark(self.init_flags(), flags, mask)?;
+        api::mark_with(&self.fd, flags, mask, dirfd, path)?;
+        self.record_path(flags, mask, dirfd, path.as_os_str());
+        Ok(())
+    }
+
+    /// Same as [`Fanotify::unmark()`] with typed flags and mask.
+    pub fn unmark_with<P: ?Sized + Path>(
+        &self,
+        flags: MarkFlags,
+        mask: EventMask,
+        dirfd: i32,
+        path: &P,
+    ) -> Result<(), FanotifyError> {
+        let flags = MarkFlags::REMOVE | flags;
+        check_mark(self.init_flags(), flags, mask)?;
+        api::mark_with(&self.fd, flags, mask, dirfd, path)?;
+        self.record_path(flags, mask, dirfd, path.as_os_str());
+        Ok(())
+    }
+
+    /// Add the events in `mask` to the mark of `target`, see [`mark_target()`].
+    /// [`FAN_MARK_ADD`] is implied.
+    pub fn mark_target(
+        &self,
+        flags: MarkFlags,
+        mask: EventMask,
+        target: MarkTarget,
+    ) -> Result<(), FanotifyError> {
+        let flags = MarkFlags::ADD | flags;
+        check_mark_target(self.init_flags(), flags, mask, &target)?;
+        api::mark_target(&self.fd, flags, mask, target)?;
+        self.record_target(flags, mask, &target);
+        Ok(())
+    }
+
+    /// Remove the events in `mask` from the mark of `target`.
+    /// [`FAN_MARK_REMOVE`] is implied.
+    pub fn unmark_target(
+        &self,
+        flags: MarkFlags,
+        mask: EventMask,
+        target: MarkTarget,
+    ) -> Result<(), FanotifyError> {
+        let flags = MarkFlags::REMOVE | flags;
+        check_mark_target(self.init_flags(), flags, mask, &target)?;
+        api::mark_target(&self.fd, flags, mask, target)?;
+        self.record_target(flags, mask, &target);
+        Ok(())
+    }
+
+    /// Remove all inode marks, or all mount marks if `flags` is
+    /// [`FAN_MARK_MOUNT`], or all filesystem marks if `flags` is
+    /// [`FAN_MARK_FILESYSTEM`].
+    pub fn flush(&self, flags: u32) -> Result<(), FanotifyError> {
+        let flags = MarkFlags::FLUSH | MarkFlags::from(flags);
+        check_mark(self.init_flags(), flags, EventMask::empty())?;
+        api::mark_with(&self.fd, flags, EventMask::empty(), AT_FDCWD, "/")?;
+        if !flags.contains(MarkFlags::from(FAN_MARK_MNTNS)) {
+            self.marks.flush(MarkKind::from_flags(flags));
+        }
+        Ok(())
+    }
+
+    /// Marks placed through this group, see [`Registry`].
+    pub fn registry(&self) -> Registry<'_> {
+        Registry::new(self)
+    }
+
+    pub(crate) fn marks(&self) -> &MarkTable {
+        &self.marks
+    }
+
+    /// Record a mark placed with `dirfd` and `path`. Objects that can't be
+    /// looked up are left for [`Registry::reconcile()`].
+    fn record_path(&self, flags: MarkFlags, mask: EventMask, dirfd: i32, path: &std::ffi::OsStr) {
+        // Mount namespace marks are not tracked.
+        if flags.contains(MarkFlags::from(FAN_MARK_MNTNS)) {
+            return;
+        }
+        let object = c_path(path).and_then(|c_path| {
+            MarkObject::lookup(MarkKind::from_flags(flags), dirfd, Some(&c_path), flags)
+        });
+        if let Ok(object) = object {
+            let path = (dirfd == AT_FDCWD).then(|| path.into());
+            self.marks.record(object, path, flags, mask);
+        }
+    }
+
+    /// Same as [`record_path()`](Self::record_path) for a [`MarkTarget`].
+    fn record_target(&self, flags: MarkFlags, mask: EventMask, target: &MarkTarget) {
+        if let Ok(object) = MarkObject::of(target, flags) {
+            let path = match target {
+                MarkTarget::Inode(path)
+                | MarkTarget::Mount(path)
+                | MarkTarget::Filesystem(path) => Some(path.into()),
+                MarkTarget::At(_, path) if std::path::Path::new(path).is_absolute() => {
+                    Some(path.into())
+                }
+                _ => None,
+            };
+            self.marks
+                .record(object, path, flags | target.flags(), mask);
+        }
+    }
+
+    /// Read pending events and decode them along with their
+    /// information records.
+    ///
+    /// Events are walked by `event_len`, so this works for every group
+    /// configuration. For a group that doesn't [report info](Self::reports_info)
+    /// [`Event::info`] is always empty.
+    pub fn read_events(&mut self) -> Result<Vec<Event>, FanotifyError> {
+        api::read_buffer(&self.fd, &mut self.buf)?;
+        // Safety: `buf` was just filled by read(2) on the group.
+        unsafe { EventIter::new(&self.buf) }.collect()
+    }
+
+    /// Same as [`Fanotify::read_events()`] without waiting for events,
+    /// returns an empty [`Vec`] instead of failing with `EAGAIN`.
+    ///
+    /// The read is sized to the pending bytes up to [`buffer_len()`](Self::buffer_len),
+    /// see [`try_read()`].
+    pub fn try_read_events(&mut self) -> Result<Vec<Event>, FanotifyError> {
+        let max_len = self.buffer_len();
+        api::try_read_buffer(&self.fd, &mut self.buf, max_len)?;
+        // Safety: `buf` was just filled by read(2) on the group.
+        unsafe { EventIter::new(&self.buf) }.collect()
+    }
+
+    /// Read until the queue is empty and return every event read.
+    ///
+    /// A single read returns only what fits in the buffer, with
+    /// edge-triggered readiness (Eg: mio) the rest would go unnoticed until
+    /// the next event arrives. The group must be initialised with
+    /// [`FAN_NONBLOCK`], otherwise this fails with [`FanotifyError::Invalid`]
+    /// instead of blocking once the queue is empty.
+    ///
+    /// Only `EAGAIN` ends the drain, any other error is returned. The events
+    /// read before it stay in the group and are returned first by the next
+    /// call, permission events among them can still be answered then.
+    pub fn drain(&mut self) -> Result<Vec<Event>, FanotifyError> {
+        if !self.init_flags().contains(InitFlags::NONBLOCK) {
+            return Err(FanotifyError::Invalid(Violation {
+                flag: "FAN_NONBLOCK".to_owned(),
+                rule: "is required to drain the queue without blocking",
+            }));
+        }
+        let mut events = mem::take(&mut self.drained);
+        loop {
+            match self.read_events() {
+                Ok(batch) => events.extend(batch),
+                Err(e) if e.errno() == Some(Errno::EAGAIN) => return Ok(events),
+                Err(e) => {
+                    self.drained = events;
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Wait at most `timeout` for events and read them, see
+    /// [`Fanotify::read_events()`].
+    ///
+    /// Returns an empty batch if no event arrived in time and `None` once
+    /// the [`ShutdownHandle`] of the group is signaled, shutdown is checked
+    /// before the queue.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<Event>>, FanotifyError> {
+        self.read_until(Some(timeout))
+    }
+
+    /// Block until events arrive and read them, or return `None` once
+    /// the [`ShutdownHandle`] of the group is signaled.
+    pub fn read_interruptible(&mut self) -> Result<Option<Vec<Event>>, FanotifyError> {
+        self.read_until(None)
+    }
+
+    /// Handle that stops [`read_timeout()`](Self::read_timeout) and
+    /// [`read_interruptible()`](Self::read_interruptible), created on first use.
+    pub fn shutdown_handle(&mut self) -> Result<ShutdownHandle, FanotifyError> {
+        match &self.shutdown {
+            Some(handle) => Ok(handle.clone()),
+            None => Ok(self.shutdown.insert(ShutdownHandle::new()?).clone()),
+        }
+    }
+
+    /// Stop the reads of this group with `handle`, Eg: to shut down
+    /// several groups at once.
+    pub fn set_shutdown_handle(&mut self, handle: ShutdownHandle) {
+        self.shutdown = Some(handle);
+    }
+
+    fn read_until(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Vec<Event>>, FanotifyError> {
+        let ready = match &self.shutdown {
+            Some(handle) => match shutdown::poll(&[handle.as_fd(), self.fd.as_fd()], timeout)? {
+                Some(0) => return Ok(None),
+                ready => ready,
+            },
+            None => shutdown::poll(&[self.fd.as_fd()], timeout)?,
+        };
+        match ready {
+            Some(_) => self.read_events().map(Some),
+            None => Ok(Some(Vec::new())),
+        }
+    }
+
+    /// Read pending events into the group buffer and iterate over them
+    /// without copying.
+    ///
+    /// Nothing is allocated once the buffer exists, which makes this the
+    /// read path for high throughput monitors. Every [`EventRef`] borrows
+    /// the buffer, so all of them must be dropped before the next read.
+    ///
+    /// # Example
+    /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+    /// ```rust
+    /// # use naughtyfy::flags::*;
+    /// # use naughtyfy::group::*;
+    /// # use naughtyfy::types::*;
+    /// match Fanotify::with_buffer_len(FAN_CLASS_NOTIF | FAN_NONBLOCK, O_RDONLY, 4096) {
+    ///     Ok(mut group) => {
+    ///         group.mark(FAN_MARK_MOUNT, FAN_OPEN, AT_FDCWD, "/").unwrap();
+    ///         std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    ///         for event in group.events().unwrap() {
+    ///             println!("{} {:?}", event.pid, event.fd().map(|fd| fd.path()));
+    ///         }
+    ///     }
+    ///     Err(e) => {
+    ///         // This can fail for multiple reason, most common being privileges.
+    ///         eprintln!("Cannot get fd due to {e}");
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&mut self) -> Result<Events<'_>, FanotifyError> {
+        api::read_buffer(&self.fd, &mut self.buf)?;
+        Events::new(&self.buf)
+    }
+
+    /// Response written for a [`PermissionRequest`] that is dropped
+    /// without being answered, [`FAN_ALLOW`] unless changed.
+    #[inline]
+    pub fn default_response(&self) -> u32 {
+        self.default_response
+    }
+
+    /// Change the response written for unanswered permission requests
+    /// (Eg: [`FAN_DENY`] for a fail closed policy).
+    pub fn set_default_response(&mut self, response: u32) {
+        self.default_response = response;
+    }
+
+    /// Turn `event` into a [`PermissionRequest`] that must be answered,
+    /// returns the event back if it's not a permission event.
+    pub fn permission(&self, event: Event) -> Result<PermissionRequest<'_>, Event> {
+        PermissionRequest::new(self, event)
+    }
+
+    /// Write a response to a permission event, see [`write()`].
+    /// [`FAN_AUDIT`] fails with [`FanotifyError::InvalidResponse`] unless
+    /// the group was initialised with [`FAN_ENABLE_AUDIT`].
+    pub fn respond(&self, response: &fanotify_response) -> Result<(), FanotifyError> {
+        self.check_audit(response.response)?;
+        api::write_checked(&self.fd, response, true).map(|_| ())
+    }
+
+    /// Write `response` with its information records for the permission
+    /// event with `fd`, see [`write_with_info()`]. [`FAN_AUDIT`] is checked
+    /// as in [`Fanotify::respond()`].
+    pub fn respond_with(&self, fd: &Fd, response: &Response) -> Result<(), FanotifyError> {
+        self.check_audit(response.response())?;
+        api::write_with_info_checked(
+            &self.fd,
+            &fanotify_response::new(fd, response.response()),
+            response.info(),
+            true,
+        )
+        .map(|_| ())
+    }
+
+    /// The kernel rejects [`FAN_AUDIT`] in a group initialised without
+    /// [`FAN_ENABLE_AUDIT`] with the same `EINVAL` as a response bit it
+    /// doesn't know, tell them apart before writing.
+    fn check_audit(&self, response: u32) -> Result<(), FanotifyError> {
+        if response & FAN_AUDIT != 0 && self.flags & FAN_ENABLE_AUDIT == 0 {
+            return Err(FanotifyError::InvalidResponse(
+                "FAN_AUDIT requires a group initialised with FAN_ENABLE_AUDIT",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsFd for Fanotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for Fanotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Registering the group fd with a mio [`Poll`](mio::Poll), the group
+/// must be initialised with [`FAN_NONBLOCK`] and read with
+/// [`Fanotify::drain()`] on every readiness event.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// use mio::{Events, Interest, Poll, Token};
+///
+/// let dir = std::env::temp_dir().join("naughtyfy-mio-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let flags = InitFlags::CLASS_NOTIF | InitFlags::NONBLOCK | InitFlags::REPORT_DFID_NAME;
+/// match Fanotify::with_flags(flags, EventFFlags::RDONLY) {
+///     Ok(mut group) => {
+///         let mut poll = Poll::new().unwrap();
+///         poll.registry()
+///             .register(&mut group, Token(0), Interest::READABLE)
+///             .unwrap();
+///         group.mark_target(MarkFlags::empty(), EventMask::CREATE, MarkTarget::inode(dir.as_path())).unwrap();
+///         std::fs::write(dir.join("a"), "").unwrap();
+///         std::fs::write(dir.join("b"), "").unwrap();
+///
+///         let mut events = Events::with_capacity(8);
+///         poll.poll(&mut events, None).unwrap();
+///         assert_eq!(group.drain().unwrap().len(), 2);
+///         poll.registry().deregister(&mut group).unwrap();
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[cfg(feature = "mio")]
+impl mio::event::Source for Fanotify {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl From<Fanotify> for OwnedFd {
+    fn from(group: Fanotify) -> Self {
+        group.fd
+    }
+}
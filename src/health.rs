@@ -0,0 +1,200 @@
+//! Watching filesystems for errors.
+//!
+//! Filesystems that support it (ext4, xfs, ...) report metadata corruption
+//! and I/O errors as [`FAN_FS_ERROR`] events on filesystem marks.
+//! [`FsHealthMonitor`] keeps a running tally per filesystem and calls back
+//! whenever one of them reports new errors.
+
+use crate::{
+    errors::*,
+    event::*,
+    fid::{statfs_fsid, FidResolver},
+    group::Fanotify,
+    mark::MarkTarget,
+    mask::*,
+    types::__kernel_fsid_t,
+    validate::Violation,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::flags::*;
+
+/// Errors reported so far by one filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsHealth {
+    /// Filesystem being watched.
+    pub fsid: __kernel_fsid_t,
+    /// A mount point of the filesystem, `None` if it couldn't be found.
+    pub mount_point: Option<PathBuf>,
+    /// Errors reported since the filesystem was added.
+    pub error_count: u64,
+    /// Error of the latest event, `None` while the filesystem is healthy.
+    pub last_error: Option<Errno>,
+    /// Inode of the latest event, `None` if it wasn't about an inode.
+    pub last_inode: Option<FidRecord>,
+}
+
+impl FsHealth {
+    /// `true` if no error was reported.
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.error_count == 0
+    }
+}
+
+/// Callback of [`FsHealthMonitor`].
+type Callback = Box<dyn FnMut(&FsHealth, &FsError) + Send>;
+
+/// Filesystem marks for [`FAN_FS_ERROR`] with errors aggregated per fsid.
+///
+/// The group reports file handles ([`FAN_REPORT_FID`]), marking a filesystem
+/// requires `CAP_SYS_ADMIN` and fails with `EOPNOTSUPP` on filesystems that
+/// don't report errors. The callback is run from
+/// [`read_events()`](Self::read_events) with the updated tally and the event.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::health::*;
+/// match FsHealthMonitor::new(|health, error| {
+///     eprintln!("{:?}: {} x {:?}", health.mount_point, error.error_count, error.error);
+/// }) {
+///     Ok(mut monitor) => match monitor.add(std::env::temp_dir()) {
+///         Ok(health) => assert!(health.is_healthy()),
+///         // Not every filesystem reports errors.
+///         Err(e) => eprintln!("Cannot watch temp dir due to {e}"),
+///     },
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub struct FsHealthMonitor {
+    group: Fanotify,
+    filesystems: HashMap<__kernel_fsid_t, FsHealth>,
+    resolver: FidResolver,
+    callback: Callback,
+}
+
+impl FsHealthMonitor {
+    /// Create a blocking group, `callback` is called for every error event.
+    pub fn new<F>(callback: F) -> Result<Self, FanotifyError>
+    where
+        F: FnMut(&FsHealth, &FsError) + Send + 'static,
+    {
+        let group = Fanotify::with_flags(
+            InitFlags::CLASS_NOTIF | InitFlags::CLOEXEC | InitFlags::REPORT_FID,
+            EventFFlags::RDONLY,
+        )?;
+        Self::with_group(group, callback)
+    }
+
+    /// Same as [`FsHealthMonitor::new()`] with a group created by the
+    /// caller (Eg: with [`FAN_NONBLOCK`]), it must report [`FAN_REPORT_FID`]
+    /// or this fails with [`FanotifyError::Invalid`].
+    pub fn with_group<F>(group: Fanotify, callback: F) -> Result<Self, FanotifyError>
+    where
+        F: FnMut(&FsHealth, &FsError) + Send + 'static,
+    {
+        if !group.init_flags().contains(InitFlags::REPORT_FID) {
+            return Err(FanotifyError::Invalid(Violation {
+                flag: "FAN_REPORT_FID".to_owned(),
+                rule: "is required to receive filesystem errors",
+            }));
+        }
+        Ok(FsHealthMonitor {
+            group,
+            filesystems: HashMap::new(),
+            resolver: FidResolver::new(),
+            callback: Box::new(callback),
+        })
+    }
+
+    /// Watch the filesystem `path` lives on. Adding a filesystem twice
+    /// keeps its tally.
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Result<&FsHealth, FanotifyError> {
+        let path = path.as_ref();
+        let fsid = statfs_fsid(path).ok_or_else(|| FanotifyError::Resolve(Errno::last()))?;
+        self.group.mark_target(
+            MarkFlags::empty(),
+            EventMask::FS_ERROR,
+            MarkTarget::filesystem(path),
+        )?;
+        let resolver = &mut self.resolver;
+        Ok(self.filesystems.entry(fsid).or_insert_with(|| FsHealth {
+            fsid,
+            mount_point: resolver.mount_point(&fsid).ok(),
+            error_count: 0,
+            last_error: None,
+            last_inode: None,
+        }))
+    }
+
+    /// Stop watching the filesystem `path` lives on and return its tally.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Result<Option<FsHealth>, FanotifyError> {
+        let path = path.as_ref();
+        let fsid = statfs_fsid(path).ok_or_else(|| FanotifyError::Resolve(Errno::last()))?;
+        self.group.unmark_target(
+            MarkFlags::empty(),
+            EventMask::FS_ERROR,
+            MarkTarget::filesystem(path),
+        )?;
+        Ok(self.filesystems.remove(&fsid))
+    }
+
+    /// Tally of the filesystem with `fsid`.
+    pub fn get(&self, fsid: &__kernel_fsid_t) -> Option<&FsHealth> {
+        self.filesystems.get(fsid)
+    }
+
+    /// Tallies of every watched filesystem, in no particular order.
+    pub fn filesystems(&self) -> impl Iterator<Item = &FsHealth> {
+        self.filesystems.values()
+    }
+
+    /// The group holding the marks.
+    #[inline]
+    pub fn group(&self) -> &Fanotify {
+        &self.group
+    }
+
+    /// Read events, update the tallies and run the callback for each error.
+    ///
+    /// Returns the errors read, including the ones of filesystems removed
+    /// since. After [`FAN_Q_OVERFLOW`] errors were lost, their count is not
+    /// known.
+    pub fn read_events(&mut self) -> Result<Vec<FsError>, FanotifyError> {
+        let events = self.group.read_events()?;
+        let mut errors = Vec::with_capacity(events.len());
+        for event in events {
+            let error = match event.fs_error() {
+                Some(error) => error,
+                None => continue,
+            };
+            if let Some(health) = self.filesystems.get_mut(&error.fsid) {
+                health.error_count += u64::from(error.error_count);
+                health.last_error = Some(error.error);
+                health.last_inode = error.inode.clone();
+                (self.callback)(health, &error);
+            }
+            errors.push(error);
+        }
+        Ok(errors)
+    }
+}
+
+impl fmt::Debug for FsHealthMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FsHealthMonitor")
+            .field("group", &self.group)
+            .field("filesystems", &self.filesystems)
+            .finish_non_exhaustive()
+    }
+}
@@ -24,8 +24,8 @@
 //!         let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
 //!         let events = read(fd).unwrap();
 //!         for event in events {
+//!             // Event fd is closed when event is dropped
 //!             println!("{:#?}",event);
-//!             close(event.fd);
 //!         }
 //!     }
 //!     Err(e) => {
@@ -37,5 +37,20 @@
 
 pub mod api;
 pub mod errors;
+pub mod event;
+pub mod fid;
 pub mod flags;
+pub mod group;
+pub mod health;
+pub mod mark;
+pub mod mask;
+pub mod permission;
+pub mod probe;
+pub mod process;
+pub mod recursive;
+pub mod registry;
+pub mod shutdown;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 pub mod types;
+pub mod validate;
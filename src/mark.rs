@@ -0,0 +1,115 @@
+//! Objects a mark can be placed on.
+//!
+//! [`mark()`] takes the object as a `dirfd` and path pair and the kind of
+//! object as [`FAN_MARK_MOUNT`] or [`FAN_MARK_FILESYSTEM`] in its flags, a
+//! forgotten flag marks the inode instead of the whole mount. [`MarkTarget`]
+//! names the object and its kind in one value.
+
+use crate::{errors::*, mask::*, types::*};
+use std::{
+    ffi::{CString, OsStr},
+    os::{
+        fd::{AsRawFd, BorrowedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{api::*, flags::*, group::Fanotify};
+
+/// Filesystem object to place a mark on.
+///
+/// Paths are resolved relative to the current working directory.
+/// A path containing a NUL byte fails with [`FanotifyError::InvalidPath`]
+/// instead of being passed to the kernel.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// # use std::os::fd::AsFd;
+/// let dir = std::fs::File::open(std::env::temp_dir()).unwrap();
+/// match Fanotify::with_flags(InitFlags::CLASS_NOTIF, EventFFlags::RDONLY) {
+///     Ok(group) => {
+///         let mask = EventMask::OPEN;
+///         group.mark_target(MarkFlags::empty(), mask, MarkTarget::mount("/")).unwrap();
+///         group.mark_target(MarkFlags::empty(), mask, MarkTarget::Fd(dir.as_fd())).unwrap();
+///         assert!(group.mark_target(MarkFlags::empty(), mask, MarkTarget::inode("a\0b")).is_err());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum MarkTarget<'a> {
+    /// The file or directory at the path.
+    Inode(&'a OsStr),
+    /// The mount containing the path, see [`FAN_MARK_MOUNT`].
+    Mount(&'a OsStr),
+    /// The filesystem containing the path, see [`FAN_MARK_FILESYSTEM`].
+    Filesystem(&'a OsStr),
+    /// The file or directory the fd refers to.
+    Fd(BorrowedFd<'a>),
+    /// The path relative to the directory the fd refers to.
+    At(BorrowedFd<'a>, &'a OsStr),
+}
+
+impl<'a> MarkTarget<'a> {
+    /// [`MarkTarget::Inode`] of `path`.
+    pub fn inode<P: ?Sized + Path>(path: &'a P) -> Self {
+        MarkTarget::Inode(path.as_os_str())
+    }
+
+    /// [`MarkTarget::Mount`] of `path`.
+    pub fn mount<P: ?Sized + Path>(path: &'a P) -> Self {
+        MarkTarget::Mount(path.as_os_str())
+    }
+
+    /// [`MarkTarget::Filesystem`] of `path`.
+    pub fn filesystem<P: ?Sized + Path>(path: &'a P) -> Self {
+        MarkTarget::Filesystem(path.as_os_str())
+    }
+
+    /// [`MarkTarget::At`] of `path` relative to `dirfd`.
+    pub fn at<P: ?Sized + Path>(dirfd: BorrowedFd<'a>, path: &'a P) -> Self {
+        MarkTarget::At(dirfd, path.as_os_str())
+    }
+
+    /// Mark flags selecting the kind of object.
+    pub fn flags(&self) -> MarkFlags {
+        match self {
+            MarkTarget::Mount(_) => MarkFlags::MOUNT,
+            MarkTarget::Filesystem(_) => MarkFlags::FILESYSTEM,
+            _ => MarkFlags::empty(),
+        }
+    }
+
+    /// `dirfd` argument of fanotify_mark(2).
+    pub(crate) fn dirfd(&self) -> RawFd {
+        match self {
+            MarkTarget::Fd(fd) | MarkTarget::At(fd, _) => fd.as_raw_fd(),
+            _ => libc::AT_FDCWD,
+        }
+    }
+
+    /// `pathname` argument of fanotify_mark(2), `None` for [`MarkTarget::Fd`].
+    pub(crate) fn path(&self) -> Result<Option<CString>, FanotifyError> {
+        match self {
+            MarkTarget::Fd(_) => Ok(None),
+            MarkTarget::Inode(path)
+            | MarkTarget::Mount(path)
+            | MarkTarget::Filesystem(path)
+            | MarkTarget::At(_, path) => c_path(path).map(Some),
+        }
+    }
+}
+
+/// `path` as a C string, failing on an interior NUL byte.
+pub(crate) fn c_path(path: &OsStr) -> Result<CString, FanotifyError> {
+    CString::new(path.as_bytes()).map_err(|_| FanotifyError::InvalidPath(path.to_os_string()))
+}
@@ -0,0 +1,401 @@
+//! Typed masks and flags.
+//!
+//! The constants of [`flags`](crate::flags) are plain integers, nothing stops
+//! an init flag from being passed as a mark flag. [`EventMask`], [`InitFlags`],
+//! [`MarkFlags`] and [`EventFFlags`] wrap them in distinct types that combine
+//! with `|`, iterate over their set bits and print as the kernel names
+//! without prefix (Eg: `OPEN|CLOSE_WRITE`).
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::mask::*;
+//! let mask = EventMask::OPEN | EventMask::CLOSE_WRITE;
+//! assert!(mask.contains(EventMask::OPEN));
+//! assert_eq!(mask.bits(), FAN_OPEN | FAN_CLOSE_WRITE);
+//! assert_eq!(mask.to_string(), "CLOSE_WRITE|OPEN");
+//! assert_eq!("OPEN|CLOSE_WRITE".parse::<EventMask>().unwrap(), mask);
+//! assert_eq!("FAN_CLOSE".parse::<EventMask>().unwrap(), EventMask::CLOSE);
+//! assert_eq!(mask.iter().count(), 2);
+//! assert!("OPEN|NOPE".parse::<EventMask>().is_err());
+//! ```
+
+use crate::flags::*;
+use std::{
+    error::Error,
+    fmt,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Sub, SubAssign},
+    str::FromStr,
+};
+
+/// Error returned when parsing a mask from a string fails,
+/// holds the name that isn't known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMaskError(pub String);
+
+impl Error for ParseMaskError {}
+
+impl fmt::Display for ParseMaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown flag name: {:?}", self.0)
+    }
+}
+
+/// Defines a newtype over the integer `$bits` with the named
+/// constants, operators, iteration and parsing shared by all masks.
+macro_rules! typed_mask {
+    (
+        $(#[$attr:meta])*
+        $name:ident($bits:ty), prefix = $prefix:literal, iter = $iter:ident;
+        $($(#[$const_attr:meta])* $flag:ident = $value:expr;)*
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct $name($bits);
+
+        impl $name {
+            $($(#[$const_attr])* pub const $flag: Self = Self($value);)*
+
+            /// Kernel names without prefix and their values.
+            const NAMES: &'static [(&'static str, $bits)] = &[$((stringify!($flag), $value),)*];
+
+            /// No bit set.
+            #[inline]
+            pub const fn empty() -> Self {
+                Self(0)
+            }
+
+            /// Wrap raw `bits`, bits without a name are kept.
+            #[inline]
+            pub const fn from_bits(bits: $bits) -> Self {
+                Self(bits)
+            }
+
+            /// Raw value passed to the kernel.
+            #[inline]
+            pub const fn bits(self) -> $bits {
+                self.0
+            }
+
+            /// `true` if no bit is set.
+            #[inline]
+            pub const fn is_empty(self) -> bool {
+                self.0 == 0
+            }
+
+            /// `true` if all bits of `other` are set.
+            #[inline]
+            pub const fn contains(self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// `true` if any bit of `other` is set.
+            #[inline]
+            pub const fn intersects(self, other: Self) -> bool {
+                self.0 & other.0 != 0
+            }
+
+            /// Set the bits of `other`.
+            #[inline]
+            pub fn insert(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+
+            /// Clear the bits of `other`.
+            #[inline]
+            pub fn remove(&mut self, other: Self) {
+                self.0 &= !other.0;
+            }
+
+            /// Iterate over the set bits, one bit at a time from the lowest.
+            #[inline]
+            pub fn iter(self) -> $iter {
+                $iter(self.0)
+            }
+
+            /// Kernel name of `value`.
+            fn name(value: $bits) -> Option<&'static str> {
+                Self::NAMES
+                    .iter()
+                    .find(|(_, known)| *known == value)
+                    .map(|(name, _)| *name)
+            }
+        }
+
+        #[doc = concat!("Iterator over the set bits of [`", stringify!($name), "`].")]
+        #[derive(Debug, Clone)]
+        pub struct $iter($bits);
+
+        impl Iterator for $iter {
+            type Item = $name;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.0 == 0 {
+                    return None;
+                }
+                let bit = self.0 & self.0.wrapping_neg();
+                self.0 &= !bit;
+                Some($name(bit))
+            }
+        }
+
+        impl IntoIterator for $name {
+            type Item = $name;
+            type IntoIter = $iter;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl From<$bits> for $name {
+            fn from(bits: $bits) -> Self {
+                Self(bits)
+            }
+        }
+
+        impl From<$name> for $bits {
+            fn from(mask: $name) -> Self {
+                mask.0
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = Self;
+
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl BitAnd for $name {
+            type Output = Self;
+
+            fn bitand(self, rhs: Self) -> Self {
+                Self(self.0 & rhs.0)
+            }
+        }
+
+        impl BitAndAssign for $name {
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.0 &= rhs.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0 & !rhs.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                self.0 &= !rhs.0;
+            }
+        }
+
+        /// Names of the set flags joined by `|` in the order they are
+        /// declared, bits without a name are written in hex. A zero value
+        /// is written with the name of the zero flag if there is one.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if self.0 == 0 {
+                    return f.write_str(Self::name(0).unwrap_or(""));
+                }
+                let mut remaining = self.0;
+                let mut first = true;
+                let mut separator = |f: &mut fmt::Formatter<'_>| {
+                    let sep = if first { "" } else { "|" };
+                    first = false;
+                    f.write_str(sep)
+                };
+                for (name, value) in Self::NAMES {
+                    if *value != 0 && self.0 & value == *value && remaining & value != 0 {
+                        separator(f)?;
+                        f.write_str(name)?;
+                        remaining &= !value;
+                    }
+                }
+                if remaining != 0 {
+                    separator(f)?;
+                    write!(f, "{:#x}", remaining)?;
+                }
+                Ok(())
+            }
+        }
+
+        /// Parses names joined by `|`, with or without the kernel
+        #[doc = concat!("prefix `", $prefix, "`. Hex values are accepted for bits without a name.")]
+        impl FromStr for $name {
+            type Err = ParseMaskError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut mask = Self::empty();
+                for part in s.split('|').map(str::trim).filter(|part| !part.is_empty()) {
+                    let name = part.strip_prefix($prefix).unwrap_or(part);
+                    let bits = match Self::NAMES.iter().find(|(known, _)| *known == name) {
+                        Some((_, value)) => *value,
+                        None => part
+                            .strip_prefix("0x")
+                            .and_then(|hex| <$bits>::from_str_radix(hex, 16).ok())
+                            .ok_or_else(|| ParseMaskError(part.to_string()))?,
+                    };
+                    mask.0 |= bits;
+                }
+                Ok(mask)
+            }
+        }
+    };
+}
+
+typed_mask! {
+    /// Events of a mark and of a read event, see the `FAN_*` event
+    /// constants of [`flags`](crate::flags).
+    EventMask(u64), prefix = "FAN_", iter = EventMaskIter;
+    /// See [`FAN_ACCESS`].
+    ACCESS = FAN_ACCESS;
+    /// See [`FAN_MODIFY`].
+    MODIFY = FAN_MODIFY;
+    /// See [`FAN_ATTRIB`].
+    ATTRIB = FAN_ATTRIB;
+    /// See [`FAN_CLOSE_WRITE`].
+    CLOSE_WRITE = FAN_CLOSE_WRITE;
+    /// See [`FAN_CLOSE_NOWRITE`].
+    CLOSE_NOWRITE = FAN_CLOSE_NOWRITE;
+    /// See [`FAN_OPEN`].
+    OPEN = FAN_OPEN;
+    /// See [`FAN_MOVED_FROM`].
+    MOVED_FROM = FAN_MOVED_FROM;
+    /// See [`FAN_MOVED_TO`].
+    MOVED_TO = FAN_MOVED_TO;
+    /// See [`FAN_CREATE`].
+    CREATE = FAN_CREATE;
+    /// See [`FAN_DELETE`].
+    DELETE = FAN_DELETE;
+    /// See [`FAN_DELETE_SELF`].
+    DELETE_SELF = FAN_DELETE_SELF;
+    /// See [`FAN_MOVE_SELF`].
+    MOVE_SELF = FAN_MOVE_SELF;
+    /// See [`FAN_OPEN_EXEC`].
+    OPEN_EXEC = FAN_OPEN_EXEC;
+    /// See [`FAN_Q_OVERFLOW`].
+    Q_OVERFLOW = FAN_Q_OVERFLOW;
+    /// See [`FAN_FS_ERROR`].
+    FS_ERROR = FAN_FS_ERROR;
+    /// See [`FAN_OPEN_PERM`].
+    OPEN_PERM = FAN_OPEN_PERM;
+    /// See [`FAN_ACCESS_PERM`].
+    ACCESS_PERM = FAN_ACCESS_PERM;
+    /// See [`FAN_OPEN_EXEC_PERM`].
+    OPEN_EXEC_PERM = FAN_OPEN_EXEC_PERM;
+    /// See [`FAN_EVENT_ON_CHILD`].
+    EVENT_ON_CHILD = FAN_EVENT_ON_CHILD;
+    /// See [`FAN_RENAME`].
+    RENAME = FAN_RENAME;
+    /// See [`FAN_ONDIR`].
+    ONDIR = FAN_ONDIR;
+    /// See [`FAN_CLOSE`].
+    CLOSE = FAN_CLOSE;
+    /// See [`FAN_MOVE`].
+    MOVE = FAN_MOVE;
+}
+
+typed_mask! {
+    /// Flags of [`init()`](crate::api::init), see the `FAN_CLASS_*`,
+    /// `FAN_REPORT_*` and other init constants of [`flags`](crate::flags).
+    InitFlags(u32), prefix = "FAN_", iter = InitFlagsIter;
+    /// See [`FAN_CLOEXEC`].
+    CLOEXEC = FAN_CLOEXEC;
+    /// See [`FAN_NONBLOCK`].
+    NONBLOCK = FAN_NONBLOCK;
+    /// See [`FAN_CLASS_NOTIF`].
+    CLASS_NOTIF = FAN_CLASS_NOTIF;
+    /// See [`FAN_CLASS_CONTENT`].
+    CLASS_CONTENT = FAN_CLASS_CONTENT;
+    /// See [`FAN_CLASS_PRE_CONTENT`].
+    CLASS_PRE_CONTENT = FAN_CLASS_PRE_CONTENT;
+    /// See [`FAN_UNLIMITED_QUEUE`].
+    UNLIMITED_QUEUE = FAN_UNLIMITED_QUEUE;
+    /// See [`FAN_UNLIMITED_MARKS`].
+    UNLIMITED_MARKS = FAN_UNLIMITED_MARKS;
+    /// See [`FAN_ENABLE_AUDIT`].
+    ENABLE_AUDIT = FAN_ENABLE_AUDIT;
+    /// See [`FAN_REPORT_PIDFD`].
+    REPORT_PIDFD = FAN_REPORT_PIDFD;
+    /// See [`FAN_REPORT_TID`].
+    REPORT_TID = FAN_REPORT_TID;
+    /// See [`FAN_REPORT_FID`].
+    REPORT_FID = FAN_REPORT_FID;
+    /// See [`FAN_REPORT_DIR_FID`].
+    REPORT_DIR_FID = FAN_REPORT_DIR_FID;
+    /// See [`FAN_REPORT_NAME`].
+    REPORT_NAME = FAN_REPORT_NAME;
+    /// See [`FAN_REPORT_TARGET_FID`].
+    REPORT_TARGET_FID = FAN_REPORT_TARGET_FID;
+    /// See [`FAN_REPORT_DFID_NAME`].
+    REPORT_DFID_NAME = FAN_REPORT_DFID_NAME;
+    /// See [`FAN_REPORT_DFID_NAME_TARGET`].
+    REPORT_DFID_NAME_TARGET = FAN_REPORT_DFID_NAME_TARGET;
+}
+
+typed_mask! {
+    /// Flags of [`mark()`](crate::api::mark), see the `FAN_MARK_*`
+    /// constants of [`flags`](crate::flags).
+    MarkFlags(u32), prefix = "FAN_MARK_", iter = MarkFlagsIter;
+    /// See [`FAN_MARK_ADD`].
+    ADD = FAN_MARK_ADD;
+    /// See [`FAN_MARK_REMOVE`].
+    REMOVE = FAN_MARK_REMOVE;
+    /// See [`FAN_MARK_DONT_FOLLOW`].
+    DONT_FOLLOW = FAN_MARK_DONT_FOLLOW;
+    /// See [`FAN_MARK_ONLYDIR`].
+    ONLYDIR = FAN_MARK_ONLYDIR;
+    /// See [`FAN_MARK_MOUNT`].
+    MOUNT = FAN_MARK_MOUNT;
+    /// See [`FAN_MARK_IGNORED_MASK`].
+    IGNORED_MASK = FAN_MARK_IGNORED_MASK;
+    /// See [`FAN_MARK_IGNORED_SURV_MODIFY`].
+    IGNORED_SURV_MODIFY = FAN_MARK_IGNORED_SURV_MODIFY;
+    /// See [`FAN_MARK_FLUSH`].
+    FLUSH = FAN_MARK_FLUSH;
+    /// See [`FAN_MARK_FILESYSTEM`].
+    FILESYSTEM = FAN_MARK_FILESYSTEM;
+    /// See [`FAN_MARK_EVICTABLE`].
+    EVICTABLE = FAN_MARK_EVICTABLE;
+    /// See [`FAN_MARK_IGNORE`].
+    IGNORE = FAN_MARK_IGNORE;
+    /// See [`FAN_MARK_IGNORE_SURV`].
+    IGNORE_SURV = FAN_MARK_IGNORE_SURV;
+}
+
+typed_mask! {
+    /// File status flags of the event fds, the `event_f_flags`
+    /// of [`init()`](crate::api::init).
+    EventFFlags(u32), prefix = "O_", iter = EventFFlagsIter;
+    /// See [`O_RDONLY`].
+    RDONLY = O_RDONLY;
+    /// See [`O_WRONLY`].
+    WRONLY = O_WRONLY;
+    /// See [`O_RDWR`].
+    RDWR = O_RDWR;
+    /// See [`O_APPEND`].
+    APPEND = O_APPEND;
+    /// See [`O_NONBLOCK`].
+    NONBLOCK = O_NONBLOCK;
+    /// See [`O_DSYNC`].
+    DSYNC = O_DSYNC;
+    /// See [`O_LARGEFILE`].
+    LARGEFILE = O_LARGEFILE;
+    /// See [`O_NOATIME`].
+    NOATIME = O_NOATIME;
+    /// See [`O_CLOEXEC`].
+    CLOEXEC = O_CLOEXEC;
+}
@@ -0,0 +1,260 @@
+//! Permission events that must be answered.
+//!
+//! With a [`FAN_CLASS_CONTENT`] or [`FAN_CLASS_PRE_CONTENT`] group every
+//! [`FAN_OPEN_PERM`], [`FAN_ACCESS_PERM`] and [`FAN_OPEN_EXEC_PERM`] event
+//! blocks the process that caused it until a response is written to the
+//! group. [`PermissionRequest`] wraps such an event and guarantees that a
+//! response is written exactly once, falling back to the default response
+//! of the group if the handler never answers.
+
+use crate::{errors::*, event::*, group::Fanotify, types::*};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::flags::*;
+
+/// Events that block until userspace writes a response.
+pub const FAN_PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM | FAN_OPEN_EXEC_PERM;
+
+/// Errnos the kernel accepts in a deny response besides `EPERM`.
+const FAN_DENY_ERRNOS: [i32; 6] = [
+    libc::EIO,
+    libc::EBUSY,
+    libc::ETXTBSY,
+    libc::EAGAIN,
+    libc::ENOSPC,
+    libc::EDQUOT,
+];
+
+/// A response to a permission event along with the information
+/// records written after it.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::permission::*;
+/// let response = Response::deny().audit_rule(7, 1, 2);
+/// assert_eq!(response.response(), FAN_DENY | FAN_AUDIT | FAN_INFO);
+/// assert_eq!(response.info().len(), 16);
+///
+/// let response = Response::deny_with_errno(libc::EBUSY).unwrap();
+/// assert_eq!(response.response(), fan_deny_errno(libc::EBUSY as u32));
+/// assert!(Response::deny_with_errno(libc::ENOENT).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    response: u32,
+    info: Vec<u8>,
+}
+
+impl Response {
+    /// Response made of the raw `response` bits (Eg: [`FAN_DENY`] | [`FAN_AUDIT`])
+    /// with no information records.
+    pub fn new(response: u32) -> Self {
+        Response {
+            response,
+            info: Vec::new(),
+        }
+    }
+
+    /// Let the operation proceed.
+    pub fn allow() -> Self {
+        Self::new(FAN_ALLOW)
+    }
+
+    /// Fail the operation with `EPERM`.
+    pub fn deny() -> Self {
+        Self::new(FAN_DENY)
+    }
+
+    /// Fail the operation with `errno` instead of `EPERM`.
+    ///
+    /// Only `EPERM`, `EIO`, `EBUSY`, `ETXTBSY`, `EAGAIN`, `ENOSPC` and `EDQUOT`
+    /// are accepted, others fail with [`FanotifyError::InvalidResponse`].
+    /// Kernel accepts this since 6.14 and only for [`FAN_CLASS_PRE_CONTENT`] groups.
+    pub fn deny_with_errno(errno: i32) -> Result<Self, FanotifyError> {
+        match errno {
+            libc::EPERM => Ok(Self::deny()),
+            errno if FAN_DENY_ERRNOS.contains(&errno) => {
+                Ok(Self::new(fan_deny_errno(errno as u32)))
+            }
+            _ => Err(FanotifyError::InvalidResponse(
+                "errno can't be reported by a deny response",
+            )),
+        }
+    }
+
+    /// Generate an audit record for the decision.
+    /// The group must be initialised with [`FAN_ENABLE_AUDIT`].
+    pub fn audit(mut self) -> Self {
+        self.response |= FAN_AUDIT;
+        self
+    }
+
+    /// Generate an audit record naming the rule that made the decision and
+    /// the trust of the subject and object (0 no, 1 yes, 2 unknown).
+    /// Kernel accepts this since 6.3.
+    pub fn audit_rule(mut self, rule_number: u32, subj_trust: u32, obj_trust: u32) -> Self {
+        let rule = fanotify_response_info_audit_rule::new(rule_number, subj_trust, obj_trust);
+        self.response |= FAN_AUDIT | FAN_INFO;
+        self.info.extend_from_slice(&rule.hdr.type_.to_ne_bytes());
+        self.info.extend_from_slice(&rule.hdr.pad.to_ne_bytes());
+        self.info.extend_from_slice(&rule.hdr.len.to_ne_bytes());
+        self.info.extend_from_slice(&rule.rule_number.to_ne_bytes());
+        self.info.extend_from_slice(&rule.subj_trust.to_ne_bytes());
+        self.info.extend_from_slice(&rule.obj_trust.to_ne_bytes());
+        self
+    }
+
+    /// Response bits written to the kernel.
+    #[inline]
+    pub fn response(&self) -> u32 {
+        self.response
+    }
+
+    /// Encoded information records written after the response.
+    #[inline]
+    pub fn info(&self) -> &[u8] {
+        &self.info
+    }
+}
+
+/// A permission event waiting for an answer.
+///
+/// Answer it with [`allow()`](Self::allow), [`deny()`](Self::deny) or
+/// [`allow_with_audit()`](Self::allow_with_audit). If it's dropped
+/// unanswered, the [default response](Fanotify::default_response)
+/// of the group is written.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::types::*;
+/// let dir = std::env::temp_dir().join("naughtyfy-perm-doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("secret"), "").unwrap();
+/// match Fanotify::new(FAN_CLASS_CONTENT, O_RDONLY) {
+///     Ok(mut group) => {
+///         group.mark(0, FAN_OPEN_PERM, AT_FDCWD, dir.join("secret").to_str().unwrap()).unwrap();
+///         let path = dir.join("secret");
+///         let opener = std::thread::spawn(move || std::fs::File::open(path));
+///
+///         for event in group.read_events().unwrap() {
+///             if let Ok(request) = group.permission(event) {
+///                 request.deny().unwrap();
+///             }
+///         }
+///         assert!(opener.join().unwrap().is_err());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PermissionRequest<'a> {
+    group: &'a Fanotify,
+    event: Event,
+    answered: bool,
+}
+
+impl<'a> PermissionRequest<'a> {
+    /// Wrap `event` of `group`, returns the event back if it is
+    /// not a permission event.
+    pub(crate) fn new(group: &'a Fanotify, event: Event) -> Result<Self, Event> {
+        if event.mask & FAN_PERM_EVENTS == 0 || event.fd().is_none() {
+            return Err(event);
+        }
+        Ok(PermissionRequest {
+            group,
+            event,
+            answered: false,
+        })
+    }
+
+    /// The event access is requested for.
+    #[inline]
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    /// Let the operation proceed.
+    pub fn allow(self) -> Result<(), FanotifyError> {
+        self.respond(FAN_ALLOW)
+    }
+
+    /// Fail the operation with `EPERM`.
+    pub fn deny(self) -> Result<(), FanotifyError> {
+        self.respond(FAN_DENY)
+    }
+
+    /// Let the operation proceed and generate an audit record for it.
+    /// The group must be initialised with [`FAN_ENABLE_AUDIT`], otherwise
+    /// this fails with [`FanotifyError::InvalidResponse`] and the request is
+    /// answered with the default response when dropped.
+    pub fn allow_with_audit(self) -> Result<(), FanotifyError> {
+        self.respond(FAN_ALLOW | FAN_AUDIT)
+    }
+
+    /// Fail the operation with `errno` instead of `EPERM`,
+    /// see [`Response::deny_with_errno()`].
+    ///
+    /// An `errno` that can't be reported leaves the request unanswered,
+    /// it's then answered with the default response when dropped.
+    pub fn deny_with_errno(self, errno: i32) -> Result<(), FanotifyError> {
+        self.respond_with(Response::deny_with_errno(errno)?)
+    }
+
+    /// Let the operation proceed and generate an audit record naming
+    /// the rule that allowed it, see [`Response::audit_rule()`].
+    pub fn allow_with_audit_rule(
+        self,
+        rule_number: u32,
+        subj_trust: u32,
+        obj_trust: u32,
+    ) -> Result<(), FanotifyError> {
+        self.respond_with(Response::allow().audit_rule(rule_number, subj_trust, obj_trust))
+    }
+
+    /// Write `response` (Eg: [`FAN_DENY`] | [`FAN_AUDIT`]) for the event.
+    pub fn respond(self, response: u32) -> Result<(), FanotifyError> {
+        self.respond_with(Response::new(response))
+    }
+
+    /// Write `response` along with its information records for the event.
+    ///
+    /// If the kernel doesn't support the response (Eg: on older kernels) the
+    /// request stays unanswered and the default response is written on drop.
+    pub fn respond_with(mut self, response: Response) -> Result<(), FanotifyError> {
+        self.write(&response)
+    }
+
+    /// Write `response` once, later calls do nothing.
+    fn write(&mut self, response: &Response) -> Result<(), FanotifyError> {
+        if self.answered {
+            return Ok(());
+        }
+        // Never retried, a failed write would fail again in drop.
+        self.answered = true;
+        let result = match self.event.fd() {
+            Some(fd) => self.group.respond_with(fd, response),
+            None => Ok(()),
+        };
+        // The kernel never saw the response, let the default one answer.
+        if let Err(FanotifyError::Unsupported(_) | FanotifyError::InvalidResponse(_)) = result {
+            self.answered = false;
+        }
+        result
+    }
+}
+
+/// Answering with the default response of the group
+impl Drop for PermissionRequest<'_> {
+    fn drop(&mut self) {
+        let _ = self.write(&Response::new(self.group.default_response()));
+    }
+}
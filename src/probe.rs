@@ -0,0 +1,258 @@
+//! Detection of the fanotify features of the running kernel.
+//!
+//! Flags are added to fanotify release by release (FID reporting in 5.1,
+//! [`FAN_REPORT_DFID_NAME`] in 5.9, [`FAN_REPORT_PIDFD`] in 5.15,
+//! [`FAN_RENAME`] in 5.17, [`FAN_MARK_IGNORE`] in 6.0 ...) and an older
+//! kernel rejects the newer ones with `EINVAL`. [`probe()`] tries every flag
+//! on throwaway groups and marks and reports what worked in [`KernelFeatures`].
+
+use crate::{api, errors::*, flags::*, mark::MarkTarget, mask::*};
+use std::path::Path;
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::event::InfoRecord;
+
+/// Init flags tried on their own, with the flags they depend on.
+const INIT_PROBES: [(InitFlags, InitFlags); 13] = [
+    (InitFlags::CLOEXEC, InitFlags::empty()),
+    (InitFlags::NONBLOCK, InitFlags::empty()),
+    (InitFlags::CLASS_CONTENT, InitFlags::empty()),
+    (InitFlags::CLASS_PRE_CONTENT, InitFlags::empty()),
+    (InitFlags::UNLIMITED_QUEUE, InitFlags::empty()),
+    (InitFlags::UNLIMITED_MARKS, InitFlags::empty()),
+    (InitFlags::ENABLE_AUDIT, InitFlags::empty()),
+    (InitFlags::REPORT_TID, InitFlags::empty()),
+    (InitFlags::REPORT_PIDFD, InitFlags::empty()),
+    (InitFlags::REPORT_FID, InitFlags::empty()),
+    (InitFlags::REPORT_DIR_FID, InitFlags::empty()),
+    (InitFlags::REPORT_NAME, InitFlags::REPORT_DIR_FID),
+    (
+        InitFlags::REPORT_TARGET_FID,
+        InitFlags::from_bits(FAN_REPORT_DFID_NAME | FAN_REPORT_FID),
+    ),
+];
+
+/// Mark flags tried with an [`FAN_OPEN`] mark, with the flags they depend on.
+const MARK_PROBES: [(MarkFlags, MarkFlags); 8] = [
+    (MarkFlags::DONT_FOLLOW, MarkFlags::empty()),
+    (MarkFlags::ONLYDIR, MarkFlags::empty()),
+    (MarkFlags::MOUNT, MarkFlags::empty()),
+    (MarkFlags::FILESYSTEM, MarkFlags::empty()),
+    (MarkFlags::IGNORED_MASK, MarkFlags::empty()),
+    (MarkFlags::IGNORED_SURV_MODIFY, MarkFlags::IGNORED_MASK),
+    (MarkFlags::EVICTABLE, MarkFlags::empty()),
+    (MarkFlags::IGNORE, MarkFlags::IGNORED_SURV_MODIFY),
+];
+
+/// Events that can be marked on a directory inode of a FID group.
+const DIR_EVENTS: [EventMask; 15] = [
+    EventMask::ACCESS,
+    EventMask::MODIFY,
+    EventMask::ATTRIB,
+    EventMask::CLOSE_WRITE,
+    EventMask::CLOSE_NOWRITE,
+    EventMask::OPEN,
+    EventMask::MOVED_FROM,
+    EventMask::MOVED_TO,
+    EventMask::CREATE,
+    EventMask::DELETE,
+    EventMask::DELETE_SELF,
+    EventMask::MOVE_SELF,
+    EventMask::OPEN_EXEC,
+    EventMask::RENAME,
+    EventMask::EVENT_ON_CHILD,
+];
+
+/// Events that need a group of [`FAN_CLASS_CONTENT`].
+const PERM_EVENTS: [EventMask; 3] = [
+    EventMask::OPEN_PERM,
+    EventMask::ACCESS_PERM,
+    EventMask::OPEN_EXEC_PERM,
+];
+
+/// What the running kernel accepted when [probed](probe()).
+///
+/// A flag missing here was rejected, either because the kernel predates
+/// it or because the caller lacks a capability it needs (Eg: `CAP_AUDIT_WRITE`
+/// for [`FAN_ENABLE_AUDIT`]) or the probed filesystem can't report it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelFeatures {
+    /// Accepted init flags.
+    pub init_flags: InitFlags,
+    /// Accepted mark flags.
+    pub mark_flags: MarkFlags,
+    /// Events (and [`FAN_ONDIR`], [`FAN_EVENT_ON_CHILD`]) that can be marked.
+    pub events: EventMask,
+    /// Information record types (`FAN_EVENT_INFO_TYPE_*`) events can carry.
+    pub info_types: Vec<u8>,
+}
+
+impl KernelFeatures {
+    /// `true` if all of `flags` were accepted by [`init()`](crate::api::init).
+    #[inline]
+    pub fn supports_init(&self, flags: InitFlags) -> bool {
+        self.init_flags.contains(flags)
+    }
+
+    /// `true` if all of `flags` were accepted by [`mark()`](crate::api::mark).
+    #[inline]
+    pub fn supports_mark(&self, flags: MarkFlags) -> bool {
+        self.mark_flags.contains(flags)
+    }
+
+    /// `true` if all events of `mask` can be marked.
+    #[inline]
+    pub fn supports_events(&self, mask: EventMask) -> bool {
+        self.events.contains(mask)
+    }
+
+    /// `true` if events can carry the [`InfoRecord`] of `info_type`
+    /// (Eg: [`FAN_EVENT_INFO_TYPE_PIDFD`]).
+    #[inline]
+    pub fn supports_info(&self, info_type: u8) -> bool {
+        self.info_types.contains(&info_type)
+    }
+}
+
+/// Detect the features of the running kernel using a directory
+/// created in [`std::env::temp_dir()`].
+///
+/// Fails only if no fanotify group can be created at all (Eg: without
+/// `CAP_SYS_ADMIN`), see [`probe_at()`].
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::probe::*;
+/// match probe() {
+///     Ok(features) => {
+///         let mut flags = InitFlags::CLASS_NOTIF;
+///         if features.supports_init(InitFlags::REPORT_DFID_NAME) {
+///             flags |= InitFlags::REPORT_DFID_NAME;
+///         }
+///         assert!(features.supports_events(EventMask::OPEN));
+///         println!("{flags} {}", features.events);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot probe due to {e}");
+///     }
+/// }
+/// ```
+pub fn probe() -> Result<KernelFeatures, FanotifyError> {
+    let dir = std::env::temp_dir().join(format!("naughtyfy-probe-{}", std::process::id()));
+    match std::fs::create_dir(&dir) {
+        Ok(()) => {
+            let features = probe_at(&dir);
+            let _ = std::fs::remove_dir(&dir);
+            features
+        }
+        // Marks are only placed, never triggered, the temp dir itself will do.
+        Err(_) => probe_at(&std::env::temp_dir()),
+    }
+}
+
+/// Same as [`probe()`] with marks placed on the existing directory `dir`.
+///
+/// FID reporting depends on the filesystem, probe on the filesystem
+/// that is going to be watched to learn what it supports.
+pub fn probe_at(dir: &Path) -> Result<KernelFeatures, FanotifyError> {
+    api::init_with(InitFlags::CLASS_NOTIF, EventFFlags::RDONLY)?;
+    let mut features = KernelFeatures {
+        init_flags: InitFlags::CLASS_NOTIF,
+        mark_flags: MarkFlags::ADD | MarkFlags::REMOVE | MarkFlags::FLUSH,
+        events: EventMask::Q_OVERFLOW | EventMask::ONDIR,
+        info_types: Vec::new(),
+    };
+
+    for (flag, requires) in INIT_PROBES {
+        if api::init_with(
+            InitFlags::CLASS_NOTIF | flag | requires,
+            EventFFlags::RDONLY,
+        )
+        .is_ok()
+        {
+            features.init_flags |= flag;
+        }
+    }
+
+    for (flag, requires) in MARK_PROBES {
+        if try_mark(
+            InitFlags::CLASS_NOTIF,
+            flag | requires,
+            EventMask::OPEN,
+            dir,
+        ) {
+            features.mark_flags |= flag;
+        }
+    }
+
+    // Events without fd need a FID group, FAN_RENAME names in the records.
+    let fid = [
+        InitFlags::REPORT_DFID_NAME | InitFlags::REPORT_FID,
+        InitFlags::REPORT_FID,
+        InitFlags::empty(),
+    ]
+    .into_iter()
+    .find(|fid| features.supports_init(*fid))
+    .unwrap_or_default();
+    for event in DIR_EVENTS {
+        if try_mark(InitFlags::CLASS_NOTIF | fid, MarkFlags::empty(), event, dir) {
+            features.events |= event;
+        }
+    }
+    for event in PERM_EVENTS {
+        if try_mark(InitFlags::CLASS_CONTENT, MarkFlags::empty(), event, dir) {
+            features.events |= event;
+        }
+    }
+    if try_mark(
+        InitFlags::CLASS_NOTIF | fid,
+        MarkFlags::FILESYSTEM,
+        EventMask::FS_ERROR,
+        dir,
+    ) {
+        features.events |= EventMask::FS_ERROR;
+    }
+
+    let info = [
+        (InitFlags::REPORT_FID, FAN_EVENT_INFO_TYPE_FID),
+        (InitFlags::REPORT_DFID_NAME, FAN_EVENT_INFO_TYPE_DFID_NAME),
+        (InitFlags::REPORT_DIR_FID, FAN_EVENT_INFO_TYPE_DFID),
+        (InitFlags::REPORT_PIDFD, FAN_EVENT_INFO_TYPE_PIDFD),
+    ];
+    for (flag, info_type) in info {
+        if features.supports_init(flag) {
+            features.info_types.push(info_type);
+        }
+    }
+    if features.supports_events(EventMask::FS_ERROR) {
+        features.info_types.push(FAN_EVENT_INFO_TYPE_ERROR);
+    }
+    if features.supports_events(EventMask::RENAME) {
+        features.info_types.push(FAN_EVENT_INFO_TYPE_OLD_DFID_NAME);
+        features.info_types.push(FAN_EVENT_INFO_TYPE_NEW_DFID_NAME);
+    }
+    features.info_types.sort_unstable();
+    Ok(features)
+}
+
+/// `true` if a group initialised with `init` accepts a mark of `mask`
+/// on `dir` with `flags`. [`FAN_MARK_ADD`] is implied.
+fn try_mark(init: InitFlags, flags: MarkFlags, mask: EventMask, dir: &Path) -> bool {
+    let target = if flags.contains(MarkFlags::MOUNT) {
+        MarkTarget::mount(dir)
+    } else if flags.contains(MarkFlags::FILESYSTEM) {
+        MarkTarget::filesystem(dir)
+    } else {
+        MarkTarget::inode(dir)
+    };
+    let flags = MarkFlags::ADD | (flags - MarkFlags::MOUNT - MarkFlags::FILESYSTEM);
+    match api::init_with(init, EventFFlags::RDONLY) {
+        Ok(fd) => api::mark_target(&fd, flags, mask, target).is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,647 @@
+//! Looking up the process that caused an event.
+//!
+//! [`Event::pid`] is only a number, by the time `/proc/<pid>` is read the
+//! process may have exited and the pid been reused. A pidfd
+//! ([`FAN_REPORT_PIDFD`]) keeps referring to the same process, so
+//! [`ProcDir::open()`] opens `/proc/<pid>` and then checks through the pidfd
+//! that the process is still alive: if so the directory can't belong to
+//! another process, reads through it fail with `ESRCH` once the process
+//! is gone.
+//!
+//! [`ProcessEnricher`] attaches a [`ProcessInfo`] to events, caching them
+//! by pidfd inode so that busy processes are read once.
+
+use crate::{errors::*, event::*};
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsString},
+    fs::File,
+    io::Read,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStringExt,
+    },
+    path::PathBuf,
+    sync::Arc,
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::flags::*;
+
+/// Pid of the process `pidfd` refers to, in the pid namespace of the caller.
+///
+/// Fails with [`FanotifyError::Resolve`] carrying `ESRCH` once the process
+/// was reaped or if it isn't visible from this namespace, and `EINVAL` if
+/// `pidfd` isn't a pidfd.
+pub fn pidfd_pid(pidfd: BorrowedFd<'_>) -> Result<i32, FanotifyError> {
+    let fdinfo = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", pidfd.as_raw_fd()))
+        .map_err(|e| FanotifyError::Resolve(Errno::from(&e)))?;
+    let pid = fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Pid:"))
+        .and_then(|pid| pid.trim().parse::<i32>().ok())
+        .ok_or(FanotifyError::Resolve(Errno::EINVAL))?;
+    // -1 once reaped, 0 from outside of its namespace.
+    if pid <= 0 {
+        return Err(FanotifyError::Resolve(Errno::ESRCH));
+    }
+    Ok(pid)
+}
+
+/// `true` until the process `pidfd` refers to is reaped, checked by
+/// sending it signal 0 with pidfd_send_signal(2).
+///
+/// Zombies count as alive, their pid can't be reused yet. Fails with
+/// `EPERM` if the caller may not signal the process.
+pub fn is_alive(pidfd: BorrowedFd<'_>) -> Result<bool, FanotifyError> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            0,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    match ret {
+        0 => Ok(true),
+        _ if Errno::last() == Errno::ESRCH => Ok(false),
+        _ => Err(FanotifyError::Resolve(Errno::last())),
+    }
+}
+
+/// `/proc/<pid>` of a process, opened so that it can't refer to another
+/// process reusing the pid.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::process::*;
+/// # use std::os::fd::AsFd;
+/// let dir = std::env::temp_dir().join("naughtyfy-process-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// match Fanotify::with_flags(
+///     InitFlags::CLASS_NOTIF | InitFlags::REPORT_FID | InitFlags::REPORT_PIDFD,
+///     EventFFlags::RDONLY,
+/// ) {
+///     Ok(mut group) => {
+///         group.mark_target(MarkFlags::empty(), EventMask::CREATE, MarkTarget::inode(dir.as_path())).unwrap();
+///         std::fs::write(dir.join("created"), "").unwrap();
+///         let events = group.read_events().unwrap();
+///         let pidfd = events[0].pidfd().unwrap();
+///         assert_eq!(pidfd_pid(pidfd.as_fd()).unwrap(), std::process::id() as i32);
+///         assert!(is_alive(pidfd.as_fd()).unwrap());
+///         let proc_dir = ProcDir::open(pidfd.as_fd()).unwrap();
+///         assert_eq!(proc_dir.exe().unwrap(), std::env::current_exe().unwrap());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ProcDir {
+    pid: i32,
+    fd: OwnedFd,
+}
+
+impl ProcDir {
+    /// Open `/proc/<pid>` of the process `pidfd` refers to.
+    ///
+    /// Fails with [`FanotifyError::Resolve`] carrying `ESRCH` if the
+    /// process is gone.
+    pub fn open(pidfd: BorrowedFd<'_>) -> Result<Self, FanotifyError> {
+        let pid = pidfd_pid(pidfd)?;
+        let proc_dir = Self::open_pid(pid)?;
+        // The pid may have been reused between reading it and opening the
+        // directory, unless the process is still there.
+        if !is_alive(pidfd)? {
+            return Err(FanotifyError::Resolve(Errno::ESRCH));
+        }
+        Ok(proc_dir)
+    }
+
+    /// Open `/proc/<pid>` by pid alone, for groups without pidfds.
+    ///
+    /// Nothing tells whether `pid` still belongs to the process that
+    /// caused the event.
+    pub fn open_pid(pid: i32) -> Result<Self, FanotifyError> {
+        let path = CString::new(format!("/proc/{pid}")).unwrap();
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        match fd {
+            -1 if Errno::last() == Errno::ENOENT => Err(FanotifyError::Resolve(Errno::ESRCH)),
+            -1 => Err(FanotifyError::Resolve(Errno::last())),
+            fd => Ok(ProcDir {
+                pid,
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            }),
+        }
+    }
+
+    /// Pid of the process.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Content of the file `name` (Eg: `"status"`) of the directory.
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, FanotifyError> {
+        let name = CString::new(name).map_err(|_| FanotifyError::Resolve(Errno::EINVAL))?;
+        let fd = unsafe {
+            libc::openat(
+                self.fd.as_raw_fd(),
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(FanotifyError::Resolve(Errno::last()));
+        }
+        let mut content = Vec::new();
+        unsafe { File::from_raw_fd(fd) }
+            .read_to_end(&mut content)
+            .map_err(|e| FanotifyError::Resolve(Errno::from(&e)))?;
+        Ok(content)
+    }
+
+    /// Path of the executable, ending with `" (deleted)"` if it was
+    /// deleted since the process started.
+    pub fn exe(&self) -> Result<PathBuf, FanotifyError> {
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let len = unsafe {
+            libc::readlinkat(
+                self.fd.as_raw_fd(),
+                c"exe".as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(FanotifyError::Resolve(Errno::last()));
+        }
+        buf.truncate(len as usize);
+        Ok(PathBuf::from(OsString::from_vec(buf)))
+    }
+
+    /// Command name of the process, truncated by kernel to 15 bytes.
+    pub fn comm(&self) -> Result<OsString, FanotifyError> {
+        let mut comm = self.read("comm")?;
+        if comm.last() == Some(&b'\n') {
+            comm.pop();
+        }
+        Ok(OsString::from_vec(comm))
+    }
+
+    /// Start time of the process in clock ticks after boot, with the pid
+    /// it tells processes apart over time.
+    pub fn start_time(&self) -> Result<u64, FanotifyError> {
+        let stat = self.read("stat")?;
+        Ok(Stat::parse(&stat)?.start_time)
+    }
+
+    /// Read everything [`ProcessInfo`] holds.
+    pub fn info(&self) -> Result<ProcessInfo, FanotifyError> {
+        let stat = Stat::parse(&self.read("stat")?)?;
+        let status = String::from_utf8_lossy(&self.read("status")?).into_owned();
+        let ids = |key: &str| -> Result<(u32, u32), FanotifyError> {
+            let mut ids = status
+                .lines()
+                .find_map(|line| line.strip_prefix(key))
+                .into_iter()
+                .flat_map(str::split_whitespace)
+                .map(str::parse::<u32>);
+            match (ids.next(), ids.next()) {
+                (Some(Ok(real)), Some(Ok(effective))) => Ok((real, effective)),
+                _ => Err(FanotifyError::Resolve(Errno::EINVAL)),
+            }
+        };
+        let (uid, euid) = ids("Uid:")?;
+        let (gid, egid) = ids("Gid:")?;
+        let cmdline = self.read("cmdline")?;
+        let argv = cmdline
+            .split(|byte| *byte == 0)
+            .map(|arg| OsString::from_vec(arg.to_vec()))
+            .collect::<Vec<_>>();
+        // Arguments are NUL terminated, not separated.
+        let argv = match argv.split_last() {
+            Some((last, args)) if last.is_empty() => args.to_vec(),
+            _ => argv,
+        };
+        Ok(ProcessInfo {
+            pid: self.pid,
+            ppid: stat.ppid,
+            start_time: stat.start_time,
+            comm: self.comm()?,
+            // Kernel threads have no executable, other users' processes
+            // need ptrace access.
+            exe: self.exe().ok(),
+            argv,
+            uid,
+            euid,
+            gid,
+            egid,
+            cgroup: self.cgroup().ok().flatten(),
+        })
+    }
+
+    /// Path of the process in the cgroup v2 hierarchy, the first
+    /// v1 hierarchy listed if it isn't on v2.
+    fn cgroup(&self) -> Result<Option<PathBuf>, FanotifyError> {
+        let cgroup = String::from_utf8_lossy(&self.read("cgroup")?).into_owned();
+        let path = |line: &str| line.splitn(3, ':').nth(2).map(PathBuf::from);
+        Ok(cgroup
+            .lines()
+            .find(|line| line.starts_with("0::"))
+            .or_else(|| cgroup.lines().next())
+            .and_then(path))
+    }
+}
+
+/// Fields of `/proc/<pid>/stat` used here.
+struct Stat {
+    ppid: i32,
+    start_time: u64,
+}
+
+impl Stat {
+    /// Parse the line, `comm` may hold any byte so fields are counted
+    /// from its closing parenthesis.
+    fn parse(stat: &[u8]) -> Result<Self, FanotifyError> {
+        let malformed = FanotifyError::Resolve(Errno::EINVAL);
+        let end = stat
+            .iter()
+            .rposition(|byte| *byte == b')')
+            .ok_or(malformed)?;
+        let fields = String::from_utf8_lossy(&stat[end + 1..]).into_owned();
+        // Fields 3 (state) onwards, see proc_pid_stat(5).
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let field = |n: usize| {
+            fields
+                .get(n - 3)
+                .ok_or(FanotifyError::Resolve(Errno::EINVAL))
+        };
+        Ok(Stat {
+            ppid: field(4)?
+                .parse()
+                .map_err(|_| FanotifyError::Resolve(Errno::EINVAL))?,
+            start_time: field(22)?
+                .parse()
+                .map_err(|_| FanotifyError::Resolve(Errno::EINVAL))?,
+        })
+    }
+}
+
+/// What is known about the process that caused an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Pid, or tid with [`FAN_REPORT_TID`].
+    pub pid: i32,
+    /// Pid of the parent at the time of the lookup.
+    pub ppid: i32,
+    /// Clock ticks after boot the process started at.
+    pub start_time: u64,
+    /// Command name, truncated to 15 bytes.
+    pub comm: OsString,
+    /// Path of the executable, `None` for kernel threads and when
+    /// the caller may not read it.
+    pub exe: Option<PathBuf>,
+    /// Command line arguments, empty for kernel threads and zombies.
+    pub argv: Vec<OsString>,
+    /// Real user ID.
+    pub uid: u32,
+    /// Effective user ID.
+    pub euid: u32,
+    /// Real group ID.
+    pub gid: u32,
+    /// Effective group ID.
+    pub egid: u32,
+    /// Cgroup of the process, see [`ProcDir::info()`].
+    pub cgroup: Option<PathBuf>,
+}
+
+/// An event with the process that caused it.
+#[derive(Debug)]
+pub struct EnrichedEvent {
+    /// The event as read from the group.
+    pub event: Event,
+    /// `None` if the process couldn't be looked up, Eg: it exited.
+    pub process: Option<Arc<ProcessInfo>>,
+}
+
+/// Looks up the process of events, keeping the last `capacity` processes.
+///
+/// Processes are told apart by the inode of a pidfd, unique for the boot
+/// since Linux 6.9 where pidfds live on pidfs, so a process seen before costs
+/// an fstat(2) and procfs is read only on a miss. Older kernels key entries by
+/// pid and start time, which reads `/proc/<pid>/stat` on every lookup. Either
+/// way a reused pid is never mistaken for the earlier process.
+///
+/// Events of groups with [`FAN_REPORT_PIDFD`] are looked up through their
+/// pidfd and dropped if the process is gone, otherwise by pid which may
+/// already belong to another process.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::process::*;
+/// let dir = std::env::temp_dir().join("naughtyfy-enricher-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// match Fanotify::with_flags(
+///     InitFlags::CLASS_NOTIF | InitFlags::REPORT_FID | InitFlags::REPORT_PIDFD,
+///     EventFFlags::RDONLY,
+/// ) {
+///     Ok(mut group) => {
+///         group.mark_target(MarkFlags::empty(), EventMask::CREATE, MarkTarget::inode(dir.as_path())).unwrap();
+///         std::fs::write(dir.join("a"), "").unwrap();
+///         std::fs::write(dir.join("b"), "").unwrap();
+///         let mut enricher = ProcessEnricher::new(64);
+///         let events = enricher.enrich(group.read_events().unwrap());
+///         let process = events[0].process.as_ref().unwrap();
+///         assert_eq!(process.pid, std::process::id() as i32);
+///         assert_eq!(process.exe.as_ref().unwrap(), &std::env::current_exe().unwrap());
+///         // Both events share the cached entry.
+///         assert_eq!(enricher.len(), 1);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ProcessEnricher {
+    cache: Lru<ProcessKey, Arc<ProcessInfo>>,
+    /// Whether pidfds live on pidfs, checked on the first pidfd.
+    pidfs: Option<bool>,
+}
+
+impl ProcessEnricher {
+    /// Create an enricher caching up to `capacity` processes (at least one).
+    pub fn new(capacity: usize) -> Self {
+        ProcessEnricher {
+            cache: Lru::new(capacity.max(1)),
+            pidfs: None,
+        }
+    }
+
+    /// Number of cached processes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// `true` if no process is cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.len() == 0
+    }
+
+    /// Forget every cached process.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Attach the process to each of `events`.
+    pub fn enrich(&mut self, events: Vec<Event>) -> Vec<EnrichedEvent> {
+        events
+            .into_iter()
+            .map(|event| EnrichedEvent {
+                process: self.lookup(&event).ok(),
+                event,
+            })
+            .collect()
+    }
+
+    /// Process that caused `event`.
+    ///
+    /// Fails with [`FanotifyError::Resolve`] carrying `ESRCH` if it exited,
+    /// for overflow events and pids outside of the caller's namespace.
+    pub fn lookup(&mut self, event: &Event) -> Result<Arc<ProcessInfo>, FanotifyError> {
+        match event.pidfd_record() {
+            Some(PidfdRecord::Fd(pidfd)) => self.lookup_pidfd(pidfd.as_fd()),
+            // The pid may already be reused.
+            Some(_) => Err(FanotifyError::Resolve(Errno::ESRCH)),
+            None => self.lookup_pid(event.pid),
+        }
+    }
+
+    /// Process `pidfd` refers to.
+    pub fn lookup_pidfd(
+        &mut self,
+        pidfd: BorrowedFd<'_>,
+    ) -> Result<Arc<ProcessInfo>, FanotifyError> {
+        let key = match self.pidfs_inode(pidfd)? {
+            Some(ino) => ProcessKey::Pidfs(ino),
+            None => {
+                let proc_dir = ProcDir::open(pidfd)?;
+                let key = ProcessKey::Start(proc_dir.pid(), proc_dir.start_time()?);
+                return self.lookup_dir(key, || Ok(proc_dir));
+            }
+        };
+        self.lookup_dir(key, || ProcDir::open(pidfd))
+    }
+
+    /// Process currently holding `pid`.
+    pub fn lookup_pid(&mut self, pid: i32) -> Result<Arc<ProcessInfo>, FanotifyError> {
+        // 0 for overflow and processes in other namespaces.
+        if pid <= 0 {
+            return Err(FanotifyError::Resolve(Errno::ESRCH));
+        }
+        match pidfd_open(pid) {
+            Ok(pidfd) => self.lookup_pidfd(pidfd.as_fd()),
+            // Before Linux 5.3.
+            Err(FanotifyError::Resolve(Errno::ENOSYS)) => {
+                let proc_dir = ProcDir::open_pid(pid)?;
+                let key = ProcessKey::Start(pid, proc_dir.start_time()?);
+                self.lookup_dir(key, || Ok(proc_dir))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Cached process with `key`, read from the directory `open` returns
+    /// on a miss.
+    fn lookup_dir<F>(&mut self, key: ProcessKey, open: F) -> Result<Arc<ProcessInfo>, FanotifyError>
+    where
+        F: FnOnce() -> Result<ProcDir, FanotifyError>,
+    {
+        if let Some(info) = self.cache.get(&key) {
+            return Ok(Arc::clone(info));
+        }
+        let info = Arc::new(open()?.info()?);
+        self.cache.insert(key, Arc::clone(&info));
+        Ok(info)
+    }
+
+    /// Inode of `pidfd`, `None` if pidfds don't live on pidfs.
+    fn pidfs_inode(&mut self, pidfd: BorrowedFd<'_>) -> Result<Option<u64>, FanotifyError> {
+        if self.pidfs.is_none() {
+            let mut buf = std::mem::MaybeUninit::<libc::statfs>::uninit();
+            if unsafe { libc::fstatfs(pidfd.as_raw_fd(), buf.as_mut_ptr()) } < 0 {
+                return Err(FanotifyError::Resolve(Errno::last()));
+            }
+            let f_type = unsafe { buf.assume_init() }.f_type;
+            // `f_type` is narrower on some targets.
+            #[allow(clippy::unnecessary_cast)]
+            let pidfs = f_type as i64 == PIDFS_MAGIC;
+            self.pidfs = Some(pidfs);
+        }
+        if self.pidfs != Some(true) {
+            return Ok(None);
+        }
+        let mut buf = std::mem::MaybeUninit::<libc::stat>::uninit();
+        if unsafe { libc::fstat(pidfd.as_raw_fd(), buf.as_mut_ptr()) } < 0 {
+            return Err(FanotifyError::Resolve(Errno::last()));
+        }
+        Ok(Some(unsafe { buf.assume_init() }.st_ino))
+    }
+}
+
+/// `f_type` of pidfs.
+const PIDFS_MAGIC: i64 = 0x50494446;
+
+/// Cache key of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ProcessKey {
+    /// Inode of its pidfds.
+    Pidfs(u64),
+    /// Pid and start time, before pidfs.
+    Start(i32, u64),
+}
+
+/// Open a pidfd for the process holding `pid` with pidfd_open(2).
+fn pidfd_open(pid: i32) -> Result<OwnedFd, FanotifyError> {
+    match unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } {
+        -1 => Err(FanotifyError::Resolve(Errno::last())),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) }),
+    }
+}
+
+/// No node, ends the use list.
+const NIL: usize = usize::MAX;
+
+/// Least recently used cache, its nodes are linked in use order by index.
+#[derive(Debug)]
+struct Lru<K, V> {
+    map: HashMap<K, usize>,
+    nodes: Vec<LruNode<K, V>>,
+    /// Most recently used node.
+    head: usize,
+    /// Least recently used node, evicted first.
+    tail: usize,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct LruNode<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+impl<K: Copy + Eq + std::hash::Hash, V> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Lru {
+            map: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+            capacity,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// Value of `key`, which becomes the most recently used.
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let index = *self.map.get(key)?;
+        self.unlink(index);
+        self.push_front(index);
+        Some(&self.nodes[index].value)
+    }
+
+    /// Insert or replace `key`, evicting the least recently used
+    /// entry when full.
+    fn insert(&mut self, key: K, value: V) {
+        if let Some(&index) = self.map.get(&key) {
+            self.nodes[index].value = value;
+            self.unlink(index);
+            self.push_front(index);
+            return;
+        }
+        let node = LruNode {
+            key,
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        let index = if self.nodes.len() < self.capacity {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        } else {
+            // The slot of the evicted node is reused.
+            let index = self.tail;
+            self.unlink(index);
+            let evicted = std::mem::replace(&mut self.nodes[index], node);
+            self.map.remove(&evicted.key);
+            index
+        };
+        self.map.insert(key, index);
+        self.push_front(index);
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let LruNode { prev, next, .. } = self.nodes[index];
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.nodes[index].prev = NIL;
+        self.nodes[index].next = self.head;
+        match self.head {
+            NIL => self.tail = index,
+            head => self.nodes[head].prev = index,
+        }
+        self.head = index;
+    }
+}
+
+impl AsFd for ProcDir {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
@@ -0,0 +1,353 @@
+//! Watching a directory tree.
+//!
+//! An inode mark with [`FAN_EVENT_ON_CHILD`] covers the immediate children
+//! of a directory only, mount and filesystem marks cover far more than a
+//! tree. [`RecursiveWatcher`] marks every directory of the tree and keeps
+//! the marks in sync as directories are created, deleted and moved.
+//!
+//! Directories are told apart by their file handle, see [`path_to_fid()`],
+//! so events are mapped back to paths without opening handles.
+
+use crate::{
+    errors::*,
+    event::*,
+    fid::{path_to_fid, FidResolver},
+    group::Fanotify,
+    mark::MarkTarget,
+    mask::*,
+    validate::Violation,
+};
+use std::{
+    collections::HashMap,
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::flags::*;
+
+/// Events the watcher needs to follow the directories of the tree.
+const TREE_EVENTS: EventMask = EventMask::from_bits(
+    FAN_CREATE | FAN_DELETE | FAN_MOVED_FROM | FAN_MOVED_TO | FAN_ONDIR | FAN_EVENT_ON_CHILD,
+);
+
+/// Bits of the mask that select objects rather than events.
+const EVENT_FLAGS: EventMask = EventMask::from_bits(FAN_ONDIR | FAN_EVENT_ON_CHILD);
+
+/// An event inside the watched tree.
+#[derive(Debug)]
+pub struct TreeEvent {
+    /// Path of the object relative to the root, empty for the root itself
+    /// and for [`FAN_Q_OVERFLOW`].
+    pub path: PathBuf,
+    /// The event as read from the group.
+    pub event: Event,
+}
+
+/// A directory entering the tree that couldn't be watched, see
+/// [`RecursiveWatcher::take_errors()`].
+#[derive(Debug)]
+pub struct WatchError {
+    /// Path of the directory relative to the root.
+    pub path: PathBuf,
+    /// Why marking it or walking it failed (Eg: `EACCES`).
+    pub error: FanotifyError,
+}
+
+/// Inode marks on every directory below a root.
+///
+/// The group reports directory file handles and entry names
+/// ([`FAN_REPORT_DFID_NAME`]). Events on files are reported through their
+/// directory, the `mask` passed to [`new()`](Self::new) selects what is
+/// returned, [`FAN_ONDIR`] includes the events on directories.
+///
+/// A directory created or moved into the tree is walked and marked when its
+/// event is read, entries created before that are not reported. A directory
+/// moved out of the tree is unmarked if its handle can be opened (which needs
+/// `CAP_DAC_READ_SEARCH`), otherwise events from it are dropped.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::recursive::*;
+/// # use std::path::Path;
+/// let root = std::env::temp_dir().join("naughtyfy-recursive-doc");
+/// # let _ = std::fs::remove_dir_all(&root);
+/// std::fs::create_dir_all(root.join("a/b")).unwrap();
+/// match RecursiveWatcher::new(&root, EventMask::CREATE | EventMask::CLOSE_WRITE) {
+///     Ok(mut watcher) => {
+///         assert_eq!(watcher.len(), 3);
+///         std::fs::write(root.join("a/b/deep"), "").unwrap();
+///         let events = watcher.read_events().unwrap();
+///         assert_eq!(events[0].path, Path::new("a/b/deep"));
+///
+///         // New directories are marked as their creation is read.
+///         std::fs::create_dir(root.join("a/c")).unwrap();
+///         assert!(watcher.read_events().unwrap().is_empty());
+///         std::fs::write(root.join("a/c/new"), "").unwrap();
+///         let events = watcher.read_events().unwrap();
+///         assert_eq!(events.last().unwrap().path, Path::new("a/c/new"));
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RecursiveWatcher {
+    group: Fanotify,
+    root: PathBuf,
+    /// Events requested by the caller.
+    mask: EventMask,
+    /// Watched directories by their file handle, paths relative to `root`.
+    dirs: HashMap<FidRecord, PathBuf>,
+    /// Opens the handles of directories moved out of the tree.
+    resolver: FidResolver,
+    /// Failures to follow directories since the last `take_errors()`.
+    errors: Vec<WatchError>,
+}
+
+impl RecursiveWatcher {
+    /// Watch the tree below `root` for the events in `mask`.
+    ///
+    /// The group is blocking, every directory is marked before this returns.
+    pub fn new<P: AsRef<Path>>(root: P, mask: EventMask) -> Result<Self, FanotifyError> {
+        let group = Fanotify::with_flags(
+            InitFlags::CLASS_NOTIF | InitFlags::CLOEXEC | InitFlags::REPORT_DFID_NAME,
+            EventFFlags::RDONLY,
+        )?;
+        Self::with_group(group, root, mask)
+    }
+
+    /// Same as [`RecursiveWatcher::new()`] with a group created by the
+    /// caller (Eg: with [`FAN_NONBLOCK`]), it must report [`FAN_REPORT_DFID_NAME`]
+    /// or this fails with [`FanotifyError::Invalid`].
+    pub fn with_group<P: AsRef<Path>>(
+        group: Fanotify,
+        root: P,
+        mask: EventMask,
+    ) -> Result<Self, FanotifyError> {
+        if !group.init_flags().contains(InitFlags::REPORT_DFID_NAME) {
+            return Err(FanotifyError::Invalid(Violation {
+                flag: "FAN_REPORT_DFID_NAME".to_owned(),
+                rule: "is required by a recursive watcher",
+            }));
+        }
+        let root = root.as_ref();
+        let root = root
+            .canonicalize()
+            .map_err(|e| FanotifyError::Resolve(Errno::from(&e)))?;
+        let mut watcher = RecursiveWatcher {
+            group,
+            root,
+            mask,
+            dirs: HashMap::new(),
+            resolver: FidResolver::new(),
+            errors: Vec::new(),
+        };
+        watcher.watch_tree(PathBuf::new())?;
+        Ok(watcher)
+    }
+
+    /// Canonical path of the root.
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The group holding the marks.
+    #[inline]
+    pub fn group(&self) -> &Fanotify {
+        &self.group
+    }
+
+    /// Number of watched directories, the root included.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    /// `true` if no directory is watched, once the root is gone.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Watched directories relative to the root, in no particular order.
+    pub fn dirs(&self) -> impl Iterator<Item = &Path> {
+        self.dirs.values().map(PathBuf::as_path)
+    }
+
+    /// Read events, update the marks for directories created, deleted
+    /// and moved, and return the events requested by the mask.
+    ///
+    /// Events from directories the watcher doesn't know about (Eg: ones
+    /// moved out of the tree) are dropped. After [`FAN_Q_OVERFLOW`] the
+    /// marks may be out of sync, call [`rescan()`](Self::rescan).
+    ///
+    /// A directory entering the tree that can't be watched doesn't fail the
+    /// batch, the error is kept for [`take_errors()`](Self::take_errors).
+    pub fn read_events(&mut self) -> Result<Vec<TreeEvent>, FanotifyError> {
+        let events = self.group.read_events()?;
+        let mut tree_events = Vec::with_capacity(events.len());
+        for event in events {
+            let mask = EventMask::from(event.mask);
+            if mask.contains(EventMask::Q_OVERFLOW) {
+                tree_events.push(TreeEvent {
+                    path: PathBuf::new(),
+                    event,
+                });
+                continue;
+            }
+            let path = match self.event_path(&event) {
+                Some(path) => path,
+                None => continue,
+            };
+            if mask.contains(EventMask::ONDIR) {
+                if let Err(error) = self.follow_dir(mask, &path) {
+                    self.errors.push(WatchError {
+                        path: path.clone(),
+                        error,
+                    });
+                }
+            }
+            if self.wants(mask) {
+                tree_events.push(TreeEvent { path, event });
+            }
+        }
+        Ok(tree_events)
+    }
+
+    /// Directories [`read_events()`](Self::read_events) failed to watch
+    /// since the last call, their subtrees are not (fully) watched.
+    pub fn take_errors(&mut self) -> Vec<WatchError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Drop every mark and walk the tree again, Eg: after [`FAN_Q_OVERFLOW`].
+    pub fn rescan(&mut self) -> Result<(), FanotifyError> {
+        self.group.flush(0)?;
+        self.dirs.clear();
+        self.errors.clear();
+        self.watch_tree(PathBuf::new())
+    }
+
+    /// `true` if the caller asked for an event of `mask`.
+    fn wants(&self, mask: EventMask) -> bool {
+        if mask.contains(EventMask::ONDIR) && !self.mask.contains(EventMask::ONDIR) {
+            return false;
+        }
+        mask.intersects(self.mask - EVENT_FLAGS)
+    }
+
+    /// Path of the object `event` is about, relative to the root.
+    fn event_path(&self, event: &Event) -> Option<PathBuf> {
+        event.info.iter().find_map(|info| match info {
+            InfoRecord::DfidName(fid) | InfoRecord::Dfid(fid) => {
+                let key = FidRecord {
+                    name: None,
+                    ..fid.clone()
+                };
+                let dir = self.dirs.get(&key)?;
+                Some(match fid.name.as_deref() {
+                    Some(name) if name != "." => dir.join(name),
+                    _ => dir.clone(),
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// Keep the marks in sync with a directory entering or leaving the tree.
+    fn follow_dir(&mut self, mask: EventMask, path: &Path) -> Result<(), FanotifyError> {
+        if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+            return self.watch_tree(path.to_path_buf());
+        }
+        if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+            let gone: Vec<FidRecord> = self
+                .dirs
+                .iter()
+                .filter(|(_, dir)| dir.starts_with(path))
+                .map(|(fid, _)| fid.clone())
+                .collect();
+            for fid in gone {
+                self.dirs.remove(&fid);
+                // Kernel drops the marks of deleted directories itself.
+                if mask.contains(EventMask::MOVED_FROM) {
+                    self.unwatch(&fid);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the mark of the directory with `fid`, wherever it is now.
+    fn unwatch(&mut self, fid: &FidRecord) {
+        if let Ok(fd) = self
+            .resolver
+            .open(fid, libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC)
+        {
+            let _ = self.group.unmark_target(
+                MarkFlags::ONLYDIR,
+                self.mark_mask(),
+                MarkTarget::Fd(fd.as_fd()),
+            );
+        }
+    }
+
+    /// Mark the directory at `rel` and every directory below it.
+    /// Directories that disappear during the walk are skipped.
+    fn watch_tree(&mut self, rel: PathBuf) -> Result<(), FanotifyError> {
+        let mut stack = vec![rel];
+        while let Some(rel) = stack.pop() {
+            let path = self.root.join(&rel);
+            match self.watch_dir(&path, rel.clone()) {
+                Err(e) if is_gone(&e) => continue,
+                result => result?,
+            }
+            let entries = match std::fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(FanotifyError::Resolve(Errno::from(&e))),
+            };
+            for entry in entries.flatten() {
+                // Symlinks to directories are not followed.
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    stack.push(rel.join(entry.file_name()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the directory at `path` and remember its handle. The handle is
+    /// taken first, a mark whose events can't be mapped back to a path is
+    /// never placed.
+    fn watch_dir(&mut self, path: &Path, rel: PathBuf) -> Result<(), FanotifyError> {
+        let fid = path_to_fid(path)?;
+        self.group.mark_target(
+            MarkFlags::ONLYDIR | MarkFlags::DONT_FOLLOW,
+            self.mark_mask(),
+            MarkTarget::inode(path),
+        )?;
+        self.dirs.insert(fid, rel);
+        Ok(())
+    }
+
+    /// Mask placed on every directory.
+    fn mark_mask(&self) -> EventMask {
+        self.mask | TREE_EVENTS
+    }
+}
+
+/// `true` if `err` means the object was removed while it was looked up.
+fn is_gone(err: &FanotifyError) -> bool {
+    matches!(
+        err.errno(),
+        Some(Errno::ENOENT | Errno::ENOTDIR | Errno::ESTALE)
+    )
+}
@@ -0,0 +1,370 @@
+//! Bookkeeping of the marks placed on a group.
+//!
+//! The kernel doesn't answer which marks a group has, except in
+//! `/proc/self/fdinfo/<fd>`. Every mark added or removed through
+//! [`Fanotify`] is recorded in its [`Registry`], which lists, updates and
+//! removes them by object. Marks changed with the raw [`mark()`] or
+//! dropped by the kernel (evictable marks, deleted inodes) are picked up
+//! by [`Registry::reconcile()`]. Mount namespace marks
+//! ([`FAN_MARK_MNTNS`](crate::flags::FAN_MARK_MNTNS)) are not recorded.
+
+use crate::{errors::*, group::Fanotify, mark::MarkTarget, mask::*};
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    mem,
+    os::fd::{AsRawFd, RawFd},
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{api::*, flags::*};
+
+/// Mark flags kept by the kernel with the mark.
+const MARK_STATE_FLAGS: MarkFlags =
+    MarkFlags::from_bits(FAN_MARK_IGNORED_SURV_MODIFY | FAN_MARK_EVICTABLE | FAN_MARK_IGNORE);
+
+/// Mark flags selecting the ignore mask.
+const IGNORE_FLAGS: MarkFlags = MarkFlags::from_bits(FAN_MARK_IGNORED_MASK | FAN_MARK_IGNORE);
+
+/// Kind of object a mark is placed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkKind {
+    /// A file or directory.
+    Inode,
+    /// A mount, see [`FAN_MARK_MOUNT`].
+    Mount,
+    /// A filesystem, see [`FAN_MARK_FILESYSTEM`].
+    Filesystem,
+}
+
+impl MarkKind {
+    /// Mark flags selecting the kind.
+    pub fn flags(&self) -> MarkFlags {
+        match self {
+            MarkKind::Inode => MarkFlags::empty(),
+            MarkKind::Mount => MarkFlags::MOUNT,
+            MarkKind::Filesystem => MarkFlags::FILESYSTEM,
+        }
+    }
+
+    /// Kind selected by [`FAN_MARK_MOUNT`] and [`FAN_MARK_FILESYSTEM`] in `flags`.
+    pub fn from_flags(flags: MarkFlags) -> Self {
+        if flags.contains(MarkFlags::MOUNT) {
+            MarkKind::Mount
+        } else if flags.contains(MarkFlags::FILESYSTEM) {
+            MarkKind::Filesystem
+        } else {
+            MarkKind::Inode
+        }
+    }
+}
+
+/// Object a mark is placed on, as the kernel identifies it in fdinfo.
+///
+/// `dev` is the kernel encoding of the device, `major << 20 | minor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkObject {
+    /// Inode `ino` on device `dev`.
+    Inode { dev: u32, ino: u64 },
+    /// Mount with the id listed in `/proc/self/mountinfo`.
+    Mount { mnt_id: u64 },
+    /// Filesystem on device `dev`.
+    Filesystem { dev: u32 },
+}
+
+impl MarkObject {
+    /// Kind of the object.
+    pub fn kind(&self) -> MarkKind {
+        match self {
+            MarkObject::Inode { .. } => MarkKind::Inode,
+            MarkObject::Mount { .. } => MarkKind::Mount,
+            MarkObject::Filesystem { .. } => MarkKind::Filesystem,
+        }
+    }
+
+    /// Look up the object `target` refers to, following a final symlink
+    /// unless `flags` holds [`FAN_MARK_DONT_FOLLOW`].
+    pub fn of(target: &MarkTarget, flags: MarkFlags) -> Result<Self, FanotifyError> {
+        let path = target.path()?;
+        Self::lookup(
+            MarkKind::from_flags(target.flags()),
+            target.dirfd(),
+            path.as_deref(),
+            flags,
+        )
+    }
+
+    /// Look up the object of `kind` at `path` relative to `dirfd`,
+    /// `dirfd` itself if `path` is `None`.
+    pub(crate) fn lookup(
+        kind: MarkKind,
+        dirfd: RawFd,
+        path: Option<&CStr>,
+        flags: MarkFlags,
+    ) -> Result<Self, FanotifyError> {
+        let mut at_flags = libc::AT_STATX_SYNC_AS_STAT;
+        if flags.contains(MarkFlags::DONT_FOLLOW) {
+            at_flags |= libc::AT_SYMLINK_NOFOLLOW;
+        }
+        if path.is_none() {
+            at_flags |= libc::AT_EMPTY_PATH;
+        }
+        let mut stat = mem::MaybeUninit::<libc::statx>::zeroed();
+        let stat = unsafe {
+            match libc::statx(
+                dirfd,
+                path.unwrap_or(c"").as_ptr(),
+                at_flags,
+                libc::STATX_INO | libc::STATX_MNT_ID,
+                stat.as_mut_ptr(),
+            ) {
+                0 => stat.assume_init(),
+                _ => return Err(FanotifyError::Resolve(Errno::last())),
+            }
+        };
+        let dev = stat.stx_dev_major << 20 | stat.stx_dev_minor;
+        Ok(match kind {
+            MarkKind::Inode => MarkObject::Inode {
+                dev,
+                ino: stat.stx_ino,
+            },
+            MarkKind::Mount => MarkObject::Mount {
+                mnt_id: stat.stx_mnt_id,
+            },
+            MarkKind::Filesystem => MarkObject::Filesystem { dev },
+        })
+    }
+}
+
+/// A mark as recorded by the [`Registry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkEntry {
+    /// The marked object.
+    pub object: MarkObject,
+    /// Path the mark was placed through, `None` for marks placed through
+    /// an fd or learned from the kernel.
+    pub path: Option<PathBuf>,
+    /// Events reported for the object.
+    pub mask: EventMask,
+    /// Events ignored for the object.
+    pub ignore_mask: EventMask,
+    /// Flags kept with the mark: [`FAN_MARK_IGNORED_SURV_MODIFY`],
+    /// [`FAN_MARK_EVICTABLE`] and [`FAN_MARK_IGNORE`].
+    pub flags: MarkFlags,
+}
+
+/// Marks of a group by object, kept in [`Fanotify`].
+#[derive(Debug, Default)]
+pub(crate) struct MarkTable(Mutex<HashMap<MarkObject, MarkEntry>>);
+
+impl MarkTable {
+    fn lock(&self) -> MutexGuard<'_, HashMap<MarkObject, MarkEntry>> {
+        // Updates never panic halfway, the table is consistent regardless.
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply a successful fanotify_mark(2) with `flags` and `mask` on `object`.
+    pub(crate) fn record(
+        &self,
+        object: MarkObject,
+        path: Option<PathBuf>,
+        flags: MarkFlags,
+        mask: EventMask,
+    ) {
+        let mut marks = self.lock();
+        let ignore = flags.intersects(IGNORE_FLAGS);
+        if flags.contains(MarkFlags::ADD) {
+            let entry = marks.entry(object).or_insert_with(|| MarkEntry {
+                object,
+                path: None,
+                mask: EventMask::empty(),
+                ignore_mask: EventMask::empty(),
+                flags: MarkFlags::empty(),
+            });
+            if ignore {
+                entry.ignore_mask |= mask;
+            } else {
+                entry.mask |= mask;
+            }
+            entry.flags |= flags & MARK_STATE_FLAGS;
+            if entry.path.is_none() {
+                entry.path = path;
+            }
+        } else if flags.contains(MarkFlags::REMOVE) {
+            if let Some(entry) = marks.get_mut(&object) {
+                if ignore {
+                    entry.ignore_mask -= mask;
+                } else {
+                    entry.mask -= mask;
+                }
+                // Kernel destroys a mark once both masks are empty.
+                if entry.mask.is_empty() && entry.ignore_mask.is_empty() {
+                    marks.remove(&object);
+                }
+            }
+        }
+    }
+
+    /// Forget the marks of `kind`, as [`FAN_MARK_FLUSH`] does.
+    pub(crate) fn flush(&self, kind: MarkKind) {
+        self.lock().retain(|object, _| object.kind() != kind);
+    }
+
+    fn get(&self, object: &MarkObject) -> Option<MarkEntry> {
+        self.lock().get(object).cloned()
+    }
+}
+
+/// View of the marks of a group, see [`Fanotify::registry()`].
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::registry::*;
+/// let dir = std::env::temp_dir();
+/// let target = MarkTarget::inode(dir.as_path());
+/// match Fanotify::with_flags(InitFlags::CLASS_NOTIF, EventFFlags::RDONLY) {
+///     Ok(group) => {
+///         group.mark_target(MarkFlags::empty(), EventMask::OPEN, target).unwrap();
+///         group.mark_target(MarkFlags::empty(), EventMask::OPEN, MarkTarget::mount("/")).unwrap();
+///         let registry = group.registry();
+///         assert_eq!(registry.list().len(), 2);
+///
+///         registry.update_mask(target, EventMask::CLOSE_WRITE).unwrap();
+///         assert_eq!(registry.get(target).unwrap().unwrap().mask, EventMask::CLOSE_WRITE);
+///         registry.flush(MarkKind::Mount).unwrap();
+///         registry.reconcile().unwrap();
+///         assert_eq!(registry.list().len(), 1);
+///         assert!(registry.remove(target).unwrap());
+///         assert!(registry.list().is_empty());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Registry<'a> {
+    group: &'a Fanotify,
+}
+
+impl<'a> Registry<'a> {
+    pub(crate) fn new(group: &'a Fanotify) -> Self {
+        Registry { group }
+    }
+
+    /// Every recorded mark, in no particular order.
+    pub fn list(&self) -> Vec<MarkEntry> {
+        self.group.marks().lock().values().cloned().collect()
+    }
+
+    /// The recorded mark of the object `target` refers to, if any.
+    pub fn get(&self, target: MarkTarget) -> Result<Option<MarkEntry>, FanotifyError> {
+        Ok(self
+            .group
+            .marks()
+            .get(&MarkObject::of(&target, MarkFlags::empty())?))
+    }
+
+    /// Remove the mark of `target` with both of its masks.
+    /// Returns `false` if no mark was recorded for it.
+    pub fn remove(&self, target: MarkTarget) -> Result<bool, FanotifyError> {
+        let entry = match self.get(target)? {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        if !entry.mask.is_empty() {
+            self.group
+                .unmark_target(MarkFlags::empty(), entry.mask, target)?;
+        }
+        if !entry.ignore_mask.is_empty() {
+            self.group
+                .unmark_target(MarkFlags::IGNORED_MASK, entry.ignore_mask, target)?;
+        }
+        Ok(true)
+    }
+
+    /// Replace the events reported for `target` with `mask`, adding
+    /// the mark if there is none. The ignore mask is left alone.
+    pub fn update_mask(&self, target: MarkTarget, mask: EventMask) -> Result<(), FanotifyError> {
+        let (old, flags) = match self.get(target)? {
+            Some(entry) => (entry.mask, entry.flags & MarkFlags::EVICTABLE),
+            None => (EventMask::empty(), MarkFlags::empty()),
+        };
+        let added = mask - old;
+        let removed = old - mask;
+        if !added.is_empty() {
+            // Adding without FAN_MARK_EVICTABLE would pin the mark.
+            self.group.mark_target(flags, added, target)?;
+        }
+        if !removed.is_empty() {
+            self.group
+                .unmark_target(MarkFlags::empty(), removed, target)?;
+        }
+        Ok(())
+    }
+
+    /// Remove every mark of `kind`, see [`Fanotify::flush()`].
+    pub fn flush(&self, kind: MarkKind) -> Result<(), FanotifyError> {
+        self.group.flush(kind.flags().bits())
+    }
+
+    /// Replace the recorded marks with the ones the kernel lists in
+    /// `/proc/self/fdinfo/<fd>`, keeping the recorded paths.
+    pub fn reconcile(&self) -> Result<(), FanotifyError> {
+        let fdinfo =
+            std::fs::read_to_string(format!("/proc/self/fdinfo/{}", self.group.as_raw_fd()))
+                .map_err(|e| FanotifyError::Read(Errno::from(&e)))?;
+        let mut marks = self.group.marks().lock();
+        let kernel: HashMap<MarkObject, MarkEntry> = fdinfo
+            .lines()
+            .filter_map(parse_fdinfo_mark)
+            .map(|mut entry| {
+                entry.path = marks
+                    .get(&entry.object)
+                    .and_then(|known| known.path.clone());
+                (entry.object, entry)
+            })
+            .collect();
+        *marks = kernel;
+        Ok(())
+    }
+}
+
+/// Decode a mark line of fanotify fdinfo, Eg:
+/// `fanotify ino:16 sdev:fe00000 mflags:0 mask:8 ignored_mask:0 ...`.
+fn parse_fdinfo_mark(line: &str) -> Option<MarkEntry> {
+    let fields = line.strip_prefix("fanotify ")?;
+    let field = |name: &str| {
+        fields
+            .split(' ')
+            .find_map(|field| field.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| u64::from_str_radix(value, 16).ok())
+    };
+    let object = if let Some(ino) = field("ino") {
+        MarkObject::Inode {
+            dev: field("sdev")? as u32,
+            ino,
+        }
+    } else if let Some(mnt_id) = field("mnt_id") {
+        MarkObject::Mount { mnt_id }
+    } else {
+        MarkObject::Filesystem {
+            dev: field("sdev")? as u32,
+        }
+    };
+    Some(MarkEntry {
+        object,
+        path: None,
+        mask: EventMask::from(field("mask")?),
+        ignore_mask: EventMask::from(field("ignored_mask")?),
+        flags: MarkFlags::from(field("mflags")? as u32),
+    })
+}
@@ -0,0 +1,136 @@
+//! Waking a thread blocked reading a group.
+//!
+//! [`ShutdownHandle`] wraps an eventfd that [`Fanotify::read_timeout()`]
+//! and [`Fanotify::read_interruptible()`] poll along with the group. Once
+//! it's signaled they return `Ok(None)` instead of waiting for events, so the
+//! reader can answer its outstanding permission events and exit.
+
+use crate::errors::*;
+use std::{
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::group::Fanotify;
+
+/// Cloneable handle stopping the reads of a group, see
+/// [`Fanotify::shutdown_handle()`].
+///
+/// Shutdown is permanent, every later read returns `Ok(None)` as well.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// let dir = std::env::temp_dir();
+/// match Fanotify::with_flags(InitFlags::CLASS_CONTENT, EventFFlags::RDONLY) {
+///     Ok(mut group) => {
+///         group.mark_target(MarkFlags::empty(), EventMask::OPEN_PERM, MarkTarget::inode(dir.as_path())).unwrap();
+///         let handle = group.shutdown_handle().unwrap();
+///         let reader = std::thread::spawn(move || loop {
+///             match group.read_interruptible().unwrap() {
+///                 Some(events) => {
+///                     // Unanswered requests get the default response when dropped.
+///                     for event in events {
+///                         let _ = group.permission(event);
+///                     }
+///                 }
+///                 None => break,
+///             }
+///         });
+///         handle.shutdown().unwrap();
+///         reader.join().unwrap();
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    fd: Arc<OwnedFd>,
+}
+
+impl ShutdownHandle {
+    /// Create a handle with a new eventfd, failing as a [read](FanotifyError::Read)
+    /// would if the fd can't be created.
+    pub fn new() -> Result<Self, FanotifyError> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(FanotifyError::Read(Errno::last()));
+        }
+        Ok(ShutdownHandle {
+            fd: Arc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+        })
+    }
+
+    /// Wake the readers of the group, they return `Ok(None)`.
+    pub fn shutdown(&self) -> Result<(), FanotifyError> {
+        let one = 1u64.to_ne_bytes();
+        let len = unsafe { libc::write(self.fd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+        // EAGAIN means the counter is saturated, which is signaled too.
+        if len < 0 && Errno::last() != Errno::EAGAIN {
+            return Err(FanotifyError::Write(Errno::last()));
+        }
+        Ok(())
+    }
+
+    /// `true` once [`shutdown()`](Self::shutdown) was called on any clone.
+    pub fn is_shutdown(&self) -> bool {
+        matches!(poll(&[self.fd.as_fd()], Some(Duration::ZERO)), Ok(Some(_)))
+    }
+}
+
+impl AsFd for ShutdownHandle {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Wait until one of `fds` is readable and return the index of the first
+/// readable one, `None` once `timeout` passes. `EINTR` restarts the wait
+/// with the time left.
+pub(crate) fn poll(
+    fds: &[BorrowedFd],
+    timeout: Option<Duration>,
+) -> Result<Option<usize>, FanotifyError> {
+    // A deadline past what Instant holds waits forever.
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|fd| libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    loop {
+        let timeout_ms = match deadline {
+            None => -1,
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                // Rounded up, a sub millisecond timeout must not spin.
+                left.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+            }
+        };
+        let ret = unsafe {
+            libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                timeout_ms,
+            )
+        };
+        match ret {
+            0 => return Ok(None),
+            ret if ret > 0 => return Ok(pollfds.iter().position(|fd| fd.revents != 0)),
+            _ if Errno::last() == Errno::EINTR => continue,
+            _ => return Err(FanotifyError::Read(Errno::last())),
+        }
+    }
+}
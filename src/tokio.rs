@@ -0,0 +1,164 @@
+//! [Tokio](https://tokio.rs) integration, enabled by the `tokio` feature.
+//!
+//! [`AsyncFanotify`] registers the fd of a [`FAN_NONBLOCK`] group with the
+//! tokio reactor and reads events once the kernel reports them. A read
+//! racing an empty queue fails with `EAGAIN`, that's handled here by
+//! waiting for the next readiness instead of surfacing as
+//! [`FanotifyError::Read`].
+
+use crate::{errors::*, event::*, group::Fanotify, mask::*, permission::Response, validate::*};
+use ::tokio::io::{unix::AsyncFd, Interest};
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{flags::*, permission::PermissionRequest};
+
+/// A nonblocking fanotify group driven by the tokio reactor.
+///
+/// Read events in batches with [`next_batch()`](Self::next_batch) or one by
+/// one through the [`Stream`] implementation. Both share the same queue, an
+/// event is yielded once by either of them.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::mark::*;
+/// # use naughtyfy::mask::*;
+/// # use naughtyfy::tokio::*;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let dir = std::env::temp_dir().join("naughtyfy-tokio-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let flags = InitFlags::CLASS_NOTIF | InitFlags::NONBLOCK | InitFlags::REPORT_DFID_NAME;
+/// match Fanotify::with_flags(flags, EventFFlags::RDONLY) {
+///     Ok(group) => {
+///         group.mark_target(MarkFlags::empty(), EventMask::CREATE, MarkTarget::inode(dir.as_path())).unwrap();
+///         let mut group = AsyncFanotify::new(group).unwrap();
+///         std::fs::write(dir.join("created"), "").unwrap();
+///         let events = group.next_batch().await.unwrap();
+///         assert_eq!(events.len(), 1);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncFanotify {
+    inner: AsyncFd<Fanotify>,
+    /// Events read by the stream and not yielded yet.
+    pending: VecDeque<Event>,
+}
+
+impl AsyncFanotify {
+    /// Register `group` with the reactor of the current tokio runtime.
+    ///
+    /// The group must be initialised with [`FAN_NONBLOCK`], otherwise this
+    /// fails with [`FanotifyError::Invalid`]. Must be called from within a
+    /// runtime with IO enabled, registration errors are reported as
+    /// [`FanotifyError::Read`].
+    pub fn new(group: Fanotify) -> Result<Self, FanotifyError> {
+        if !group.init_flags().contains(InitFlags::NONBLOCK) {
+            return Err(FanotifyError::Invalid(Violation {
+                flag: "FAN_NONBLOCK".to_owned(),
+                rule: "is required to read the group from an async runtime",
+            }));
+        }
+        // fanotify fds only ever poll readable, responses never block.
+        let inner = AsyncFd::with_interest(group, Interest::READABLE).map_err(read_error)?;
+        Ok(AsyncFanotify {
+            inner,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// The wrapped group.
+    #[inline]
+    pub fn get_ref(&self) -> &Fanotify {
+        self.inner.get_ref()
+    }
+
+    /// The wrapped group, Eg: to add marks.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut Fanotify {
+        self.inner.get_mut()
+    }
+
+    /// Deregister from the reactor and return the group.
+    pub fn into_inner(self) -> Fanotify {
+        self.inner.into_inner()
+    }
+
+    /// Wait until events are available and read them, see
+    /// [`Fanotify::read_events()`].
+    ///
+    /// Events already read by the [`Stream`] are returned first.
+    /// Cancelling the future loses no events.
+    pub async fn next_batch(&mut self) -> Result<Vec<Event>, FanotifyError> {
+        if !self.pending.is_empty() {
+            return Ok(self.pending.drain(..).collect());
+        }
+        loop {
+            let mut guard = self.inner.readable_mut().await.map_err(read_error)?;
+            match guard.get_inner_mut().read_events() {
+                Err(e) if e.errno() == Some(Errno::EAGAIN) => guard.clear_ready(),
+                result => return result,
+            }
+        }
+    }
+
+    /// Write `response` for the permission `event`, see
+    /// [`Fanotify::respond_with()`].
+    ///
+    /// Unlike [`PermissionRequest`] this doesn't borrow the group, so
+    /// events can be moved to other tasks and answered from there.
+    /// An event without fd fails with [`FanotifyError::InvalidResponse`].
+    pub async fn respond(&self, event: &Event, response: &Response) -> Result<(), FanotifyError> {
+        let fd = event.fd().ok_or(FanotifyError::InvalidResponse(
+            "event has no fd to respond to",
+        ))?;
+        // The kernel takes responses without blocking, nothing to wait for.
+        self.get_ref().respond_with(fd, response)
+    }
+}
+
+/// Yielding decoded events one by one, it never ends
+impl Stream for AsyncFanotify {
+    type Item = Result<Event, FanotifyError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            let mut guard = match this.inner.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(read_error(e)))),
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.get_inner_mut().read_events() {
+                Ok(events) => this.pending.extend(events),
+                Err(e) if e.errno() == Some(Errno::EAGAIN) => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+/// Reactor errors as [`FanotifyError::Read`].
+fn read_error(e: io::Error) -> FanotifyError {
+    FanotifyError::Read(Errno::from(&e))
+}
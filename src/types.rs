@@ -10,9 +10,14 @@ pub use std::os::fd::OwnedFd as Fd;
 #[allow(unused_imports)]
 use crate::api::*;
 #[allow(unused_imports)]
+use crate::event::Event;
+#[allow(unused_imports)]
 use crate::flags::*;
 
 /// After a successful read(2), the read buffer contains the following structure
+///
+/// This is a plain copy of kernel memory, it doesn't own `fd`.
+/// Events returned by [`read()`] are [`Event`]s which do.
 #[derive(Debug, Clone)]
 #[allow(non_camel_case_types)]
 #[repr(C)]
@@ -60,32 +65,25 @@ pub struct fanotify_event_metadata {
     pub pid: __s32,
 }
 
-/// Droping bounded file description
-impl Drop for fanotify_event_metadata {
-    fn drop(&mut self) {
-        if self.fd >= 0 {
-            if let Err(e) = close(self.fd) {
-                eprintln!("Fd :: {}\nErr :: {}", self.fd, e);
-            }
-        }
-    }
-}
-
-/// To be used within [`fanotify_event_info_fid`]
-#[derive(Debug, Clone, Copy)]
+/// To be used within [`fanotify_event_info_fid`]. <br>
+/// Same value as `f_fsid` returned by statfs(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
-struct __kernel_fsid_t {
-    #[allow(dead_code)]
-    val: [c_int; 2],
+#[repr(C)]
+pub struct __kernel_fsid_t {
+    pub val: [c_int; 2],
 }
 
-/// This is the header part of [`fanotify_event_info_fid`]
+/// This is the header part of every information record
+/// (Eg: [`fanotify_event_info_fid`]) that follows [`fanotify_event_metadata`]
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct fanotify_event_info_header {
+    /// Type of the record, one of `FAN_EVENT_INFO_TYPE_*` (Eg: [`FAN_EVENT_INFO_TYPE_FID`])
     pub info_type: __u8,
     pub pad: __u8,
+    /// Size of the record including this header
     pub len: __u16,
 }
 
@@ -118,13 +116,13 @@ pub struct fanotify_event_info_fid {
     /// record including the fanotify_event_info_header itself.
     /// The total size of all additional information records is
     /// not expected to be bigger than ( event_len - metadata_len ).
-    hdr: fanotify_event_info_header,
+    pub hdr: fanotify_event_info_header,
 
     /// This is a unique identifier of the filesystem containing
     /// the object associated with the event.  It is a structure
     /// of type __kernel_fsid_t and contains the same value as
     /// f_fsid when calling statfs(2).
-    fsid: __kernel_fsid_t,
+    pub fsid: __kernel_fsid_t,
 
     /// This is a variable length structure of type struct
     /// file_handle.  It is an opaque handle that corresponds to a
@@ -153,12 +151,79 @@ pub struct fanotify_event_info_fid {
     /// by a null terminated string that identifies the name of a
     /// directory entry in that directory, or '.' to identify the
     /// directory object itself.
-    file_handle: __u8,
+    ///
+    /// Zero sized, marks where the variable length [`file_handle`]
+    /// starts inside the record.
+    pub file_handle: [__u8; 0],
+}
+
+/// Header of the variable length file handle that follows `fsid`
+/// in [`fanotify_event_info_fid`], see name_to_handle_at(2).
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct file_handle {
+    /// Size of `f_handle` in bytes
+    pub handle_bytes: __u32,
+    /// Filesystem specific type of the handle
+    pub handle_type: c_int,
+    /// Zero sized, marks where `handle_bytes` bytes of handle start.
+    pub f_handle: [__u8; 0],
+}
+
+/// Information record of type [`FAN_EVENT_INFO_TYPE_PIDFD`],
+/// attached when group is initialised with [`FAN_REPORT_PIDFD`].
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_event_info_pidfd {
+    pub hdr: fanotify_event_info_header,
+    /// pidfd(2) for the process that generated the event.
+    pub pidfd: __s32,
+}
+
+/// Information record of type [`FAN_EVENT_INFO_TYPE_ERROR`],
+/// attached to [`FAN_FS_ERROR`] events.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_event_info_error {
+    pub hdr: fanotify_event_info_header,
+    /// Errno of the first error since last event was read.
+    pub error: __s32,
+    /// Number of errors reported since last event was read.
+    pub error_count: __u32,
+}
+
+/// Information record of type [`FAN_EVENT_INFO_TYPE_RANGE`],
+/// describes the file range a pre content event is about.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_event_info_range {
+    pub hdr: fanotify_event_info_header,
+    pub pad: __u32,
+    pub offset: __u64,
+    pub count: __u64,
+}
+
+/// Information record of type [`FAN_EVENT_INFO_TYPE_MNT`],
+/// attached to mount namespace events.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_event_info_mnt {
+    pub hdr: fanotify_event_info_header,
+    /// Unique id of the mount, see statmount(2).
+    pub mnt_id: __u64,
 }
 
 /// A struct that holds both [`fanotify_event_metadata`] and [`fanotify_event_info_fid`]
 /// in sequence for reading from buffer when fanotify is initialized with
 /// [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag
+#[deprecated(
+    note = "events have variable length, use `event::Event` returned by `read_with_fid()`"
+)]
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -198,6 +263,51 @@ impl fanotify_response {
     }
 }
 
+/// Header of an information record following [`fanotify_response`]
+/// when the response has the [`FAN_INFO`] bit set.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_response_info_header {
+    /// Type of the record, one of `FAN_RESPONSE_INFO_*`
+    /// (Eg: [`FAN_RESPONSE_INFO_AUDIT_RULE`]).
+    pub type_: __u8,
+    pub pad: __u8,
+    /// Size of the record including this header
+    pub len: __u16,
+}
+
+/// Information record of type [`FAN_RESPONSE_INFO_AUDIT_RULE`], names the
+/// rule that made the decision in the audit record of the response.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct fanotify_response_info_audit_rule {
+    pub hdr: fanotify_response_info_header,
+    /// Number of the rule that made the decision.
+    pub rule_number: __u32,
+    /// Trust of the subject (process), 0 no, 1 yes, 2 unknown.
+    pub subj_trust: __u32,
+    /// Trust of the object (file), 0 no, 1 yes, 2 unknown.
+    pub obj_trust: __u32,
+}
+
+impl fanotify_response_info_audit_rule {
+    /// Create a new audit rule record with the header filled in.
+    pub fn new(rule_number: __u32, subj_trust: __u32, obj_trust: __u32) -> Self {
+        fanotify_response_info_audit_rule {
+            hdr: fanotify_response_info_header {
+                type_: FAN_RESPONSE_INFO_AUDIT_RULE,
+                pad: 0,
+                len: std::mem::size_of::<Self>() as __u16,
+            },
+            rule_number,
+            subj_trust,
+            obj_trust,
+        }
+    }
+}
+
 /// Converts the implemented types to [`OsStr`] using `as_os_str()` method. <br>
 /// This is *NOT* [`std::path::Path`]
 ///
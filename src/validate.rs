@@ -0,0 +1,307 @@
+//! Checks of init and mark flag combinations before they reach the kernel.
+//!
+//! The kernel rejects most invalid combinations with a bare `EINVAL`. The
+//! checks here follow the rules of fanotify_init(2) and fanotify_mark(2) and
+//! return [`FanotifyError::Invalid`] naming the offending bit and the rule it
+//! breaks. [`Fanotify`] runs them on every call, they can also be used
+//! with the raw [`init()`] and [`mark()`].
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::errors::*;
+//! # use naughtyfy::mask::*;
+//! # use naughtyfy::validate::*;
+//! let init = InitFlags::CLASS_NOTIF | InitFlags::REPORT_FID;
+//! let err = check_mark(init, MarkFlags::ADD, EventMask::RENAME).unwrap_err();
+//! match err {
+//!     FanotifyError::Invalid(violation) => assert_eq!(violation.flag, "FAN_RENAME"),
+//!     _ => unreachable!(),
+//! }
+//! assert!(check_mark(init, MarkFlags::ADD | MarkFlags::MOUNT, EventMask::OPEN).is_ok());
+//! assert!(check_mark(init, MarkFlags::ADD | MarkFlags::MOUNT, EventMask::CREATE).is_err());
+//! ```
+
+use crate::{errors::*, mark::MarkTarget, mask::*};
+use std::{fmt, mem};
+
+// For documentaton linking
+#[allow(unused_imports)]
+use crate::{api::*, flags::*, group::Fanotify};
+
+/// Events that carry an fd and can be reported to any group.
+const FD_EVENTS: EventMask = EventMask::from_bits(
+    FAN_ACCESS
+        | FAN_MODIFY
+        | FAN_CLOSE
+        | FAN_OPEN
+        | FAN_OPEN_EXEC
+        | FAN_OPEN_PERM
+        | FAN_ACCESS_PERM
+        | FAN_OPEN_EXEC_PERM,
+);
+
+/// Bits of the mask that select objects rather than events.
+const EVENT_FLAGS: EventMask = EventMask::from_bits(FAN_ONDIR | FAN_EVENT_ON_CHILD);
+
+/// Events that block until a response is written.
+const PERM_EVENTS: EventMask =
+    EventMask::from_bits(FAN_OPEN_PERM | FAN_ACCESS_PERM | FAN_OPEN_EXEC_PERM);
+
+/// Init flags that make the group report file handles.
+const FID_FLAGS: InitFlags = InitFlags::from_bits(
+    FAN_REPORT_FID | FAN_REPORT_DIR_FID | FAN_REPORT_NAME | FAN_REPORT_TARGET_FID,
+);
+
+/// Mark flags selecting the kind of object.
+const TYPE_FLAGS: MarkFlags = MarkFlags::from_bits(FAN_MARK_MOUNT | FAN_MARK_FILESYSTEM);
+
+/// Mark flags selecting the modification.
+const ACTION_FLAGS: MarkFlags =
+    MarkFlags::from_bits(FAN_MARK_ADD | FAN_MARK_REMOVE | FAN_MARK_FLUSH);
+
+/// A broken rule, held by [`FanotifyError::Invalid`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Kernel name of the offending bit (Eg: `"FAN_RENAME"`).
+    pub flag: String,
+    /// The rule it breaks, reads as a sentence after the flag.
+    pub rule: &'static str,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}.", self.flag, self.rule)
+    }
+}
+
+/// Check the init flags of a new group.
+pub fn check_init(flags: InitFlags) -> Result<(), FanotifyError> {
+    if flags.contains(InitFlags::CLASS_CONTENT | InitFlags::CLASS_PRE_CONTENT) {
+        return invalid(
+            init_name(InitFlags::CLASS_PRE_CONTENT),
+            "can't be combined with FAN_CLASS_CONTENT, a group has one notification class",
+        );
+    }
+    if flags.contains(InitFlags::REPORT_NAME) && !flags.contains(InitFlags::REPORT_DIR_FID) {
+        return invalid(
+            init_name(InitFlags::REPORT_NAME),
+            "requires FAN_REPORT_DIR_FID",
+        );
+    }
+    if flags.contains(InitFlags::REPORT_TARGET_FID)
+        && !flags.contains(InitFlags::REPORT_FID | InitFlags::REPORT_DFID_NAME)
+    {
+        return invalid(
+            init_name(InitFlags::REPORT_TARGET_FID),
+            "requires FAN_REPORT_FID and FAN_REPORT_DFID_NAME",
+        );
+    }
+    if flags.contains(InitFlags::REPORT_PIDFD | InitFlags::REPORT_TID) {
+        return invalid(
+            init_name(InitFlags::REPORT_TID),
+            "can't be combined with FAN_REPORT_PIDFD",
+        );
+    }
+    let fid = flags & FID_FLAGS;
+    if !fid.is_empty() && flags.intersects(InitFlags::CLASS_CONTENT | InitFlags::CLASS_PRE_CONTENT)
+    {
+        return invalid(
+            init_name(fid),
+            "requires FAN_CLASS_NOTIF, permission events can't be reported with file handles",
+        );
+    }
+    Ok(())
+}
+
+/// Check `flags` and `mask` of a mark on a group initialised with `init`.
+/// The kind of object is taken from [`FAN_MARK_MOUNT`] and
+/// [`FAN_MARK_FILESYSTEM`] in `flags`, both of them is [`FAN_MARK_MNTNS`]
+/// whose mask is left for the kernel to check.
+pub fn check_mark(init: InitFlags, flags: MarkFlags, mask: EventMask) -> Result<(), FanotifyError> {
+    let action = flags & ACTION_FLAGS;
+    if action.iter().count() != 1 {
+        return invalid(
+            mark_name(if action.is_empty() {
+                MarkFlags::ADD
+            } else {
+                action
+            }),
+            "must be the only one of FAN_MARK_ADD, FAN_MARK_REMOVE and FAN_MARK_FLUSH",
+        );
+    }
+    if action == MarkFlags::FLUSH {
+        let extra = flags - ACTION_FLAGS - TYPE_FLAGS;
+        if !extra.is_empty() {
+            return invalid(
+                mark_name(extra),
+                "can't be combined with FAN_MARK_FLUSH, only FAN_MARK_MOUNT or FAN_MARK_FILESYSTEM can",
+            );
+        }
+        // Mask is ignored by flush.
+        return Ok(());
+    }
+    if mask.is_empty() {
+        return invalid(mark_name(action), "requires a non empty mask");
+    }
+    // Mount namespace marks report their own events, the kernel checks them.
+    if flags.contains(TYPE_FLAGS) {
+        return Ok(());
+    }
+    if flags.contains(MarkFlags::IGNORE | MarkFlags::IGNORED_MASK) {
+        return invalid(
+            mark_name(MarkFlags::IGNORE),
+            "can't be combined with FAN_MARK_IGNORED_MASK",
+        );
+    }
+    let inode = !flags.intersects(TYPE_FLAGS);
+    if flags.contains(MarkFlags::EVICTABLE) && !inode {
+        return invalid(
+            mark_name(MarkFlags::EVICTABLE),
+            "only applies to inode marks, mounts and filesystems can't be evicted",
+        );
+    }
+    if flags.contains(MarkFlags::IGNORE)
+        && !inode
+        && !flags.contains(MarkFlags::IGNORED_SURV_MODIFY)
+    {
+        return invalid(
+            mark_name(MarkFlags::IGNORE),
+            "requires FAN_MARK_IGNORED_SURV_MODIFY on a mount or filesystem mark",
+        );
+    }
+    let perm = mask & PERM_EVENTS;
+    if !perm.is_empty() && !init.intersects(InitFlags::CLASS_CONTENT | InitFlags::CLASS_PRE_CONTENT)
+    {
+        return invalid(
+            event_name(perm),
+            "requires a group of FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT",
+        );
+    }
+    let fid_events = mask - FD_EVENTS - EVENT_FLAGS;
+    if !fid_events.is_empty() && !init.intersects(FID_FLAGS) {
+        return invalid(
+            event_name(fid_events),
+            "carries no fd and requires a group initialised with FAN_REPORT_FID or FAN_REPORT_DIR_FID",
+        );
+    }
+    if !fid_events.is_empty() && flags.contains(MarkFlags::MOUNT) {
+        return invalid(
+            event_name(fid_events),
+            "can't be reported for a mount mark, mark the filesystem instead",
+        );
+    }
+    if mask.contains(EventMask::RENAME) && !init.contains(InitFlags::REPORT_NAME) {
+        return invalid(
+            event_name(EventMask::RENAME),
+            "requires a group initialised with FAN_REPORT_DFID_NAME",
+        );
+    }
+    if mask.contains(EventMask::FS_ERROR) && !flags.contains(MarkFlags::FILESYSTEM) {
+        return invalid(
+            event_name(EventMask::FS_ERROR),
+            "can only be reported for a filesystem mark",
+        );
+    }
+    Ok(())
+}
+
+/// Same as [`check_mark()`] with the object given by `target`. For inode
+/// marks the object is looked up to apply the rules that depend on
+/// whether it is a directory, lookup failures are left for the kernel
+/// to report.
+pub fn check_mark_target(
+    init: InitFlags,
+    flags: MarkFlags,
+    mask: EventMask,
+    target: &MarkTarget,
+) -> Result<(), FanotifyError> {
+    check_target_flags(flags)?;
+    let flags = flags | target.flags();
+    check_mark(init, flags, mask)?;
+    if !flags.contains(MarkFlags::ADD) || !target.flags().is_empty() {
+        return Ok(());
+    }
+    let is_dir = match is_dir(target, flags.contains(MarkFlags::DONT_FOLLOW)) {
+        Some(is_dir) => is_dir,
+        None => return Ok(()),
+    };
+    if is_dir
+        && flags.contains(MarkFlags::IGNORE)
+        && !flags.contains(MarkFlags::IGNORED_SURV_MODIFY)
+    {
+        return invalid(
+            mark_name(MarkFlags::IGNORE),
+            "requires FAN_MARK_IGNORED_SURV_MODIFY on a directory",
+        );
+    }
+    let event_flags = mask & EVENT_FLAGS;
+    if !is_dir && flags.contains(MarkFlags::IGNORE) && !event_flags.is_empty() {
+        return invalid(
+            event_name(event_flags),
+            "can only be set in the ignore mask of a directory",
+        );
+    }
+    if !is_dir && mask.contains(EventMask::RENAME) {
+        return invalid(
+            event_name(EventMask::RENAME),
+            "can only be reported for a directory, a file mark never sees it",
+        );
+    }
+    Ok(())
+}
+
+/// Kind of object must come from the [`MarkTarget`] alone.
+pub(crate) fn check_target_flags(flags: MarkFlags) -> Result<(), FanotifyError> {
+    if flags.intersects(TYPE_FLAGS) {
+        return invalid(
+            mark_name(flags & TYPE_FLAGS),
+            "is chosen by the MarkTarget and can't be passed in flags",
+        );
+    }
+    Ok(())
+}
+
+/// `true` if the inode `target` refers to is a directory,
+/// `None` if it can't be looked up.
+fn is_dir(target: &MarkTarget, dont_follow: bool) -> Option<bool> {
+    let path = target.path().ok()?;
+    let mut stat = mem::MaybeUninit::<libc::stat>::uninit();
+    let ret = unsafe {
+        match &path {
+            Some(path) => libc::fstatat(
+                target.dirfd(),
+                path.as_ptr(),
+                stat.as_mut_ptr(),
+                if dont_follow {
+                    libc::AT_SYMLINK_NOFOLLOW
+                } else {
+                    0
+                },
+            ),
+            None => libc::fstat(target.dirfd(), stat.as_mut_ptr()),
+        }
+    };
+    match ret {
+        0 => Some(unsafe { stat.assume_init() }.st_mode & libc::S_IFMT == libc::S_IFDIR),
+        _ => None,
+    }
+}
+
+/// Kernel name of the lowest bit of `flags`.
+fn init_name(flags: InitFlags) -> String {
+    format!("FAN_{}", flags.iter().next().unwrap_or_default())
+}
+
+/// Kernel name of the lowest bit of `flags`.
+fn mark_name(flags: MarkFlags) -> String {
+    format!("FAN_MARK_{}", flags.iter().next().unwrap_or_default())
+}
+
+/// Kernel name of the lowest bit of `mask`.
+fn event_name(mask: EventMask) -> String {
+    format!("FAN_{}", mask.iter().next().unwrap_or_default())
+}
+
+fn invalid(flag: String, rule: &'static str) -> Result<(), FanotifyError> {
+    Err(FanotifyError::Invalid(Violation { flag, rule }))
+}